@@ -333,6 +333,65 @@ impl O0 {
     pub fn write_binary(&self, w: &mut impl Write) -> std::io::Result<()> {
         self.write_to(w)
     }
+
+    /// Encoded size of every section and function of this file
+    pub fn size_report(&self) -> SizeReport {
+        let functions = self
+            .functions
+            .iter()
+            .map(|f| {
+                let name = match self.constants.get(f.name_idx as usize) {
+                    Some(Constant::String(s)) => String::from_utf8_lossy(s).into_owned(),
+                    _ => format!("#{}", f.name_idx),
+                };
+                (name, encoded_len(f))
+            })
+            .collect();
+        SizeReport {
+            header: encoded_len(&MAGIC) + encoded_len(&self.version),
+            constants: encoded_len(&self.constants),
+            start_code: encoded_len(&self.start_code),
+            function_table: encoded_len(&self.functions),
+            functions,
+        }
+    }
+}
+
+/// Sizes in bytes of the parts of an encoded o0 file
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SizeReport {
+    /// Magic number and version
+    pub header: usize,
+    pub constants: usize,
+    pub start_code: usize,
+    /// The whole function table, including its length prefix
+    pub function_table: usize,
+    /// Name and size of each function, in file order
+    pub functions: Vec<(String, usize)>,
+}
+
+impl SizeReport {
+    /// Number of functions listed as the largest ones
+    pub const TOP_N: usize = 5;
+
+    pub fn total(&self) -> usize {
+        self.header + self.constants + self.start_code + self.function_table
+    }
+
+    /// The `n` largest functions, largest first. Ties keep file order.
+    pub fn largest(&self, n: usize) -> Vec<&(String, usize)> {
+        let mut fns: Vec<_> = self.functions.iter().collect();
+        fns.sort_by_key(|f| std::cmp::Reverse(f.1));
+        fns.truncate(n);
+        fns
+    }
+}
+
+fn encoded_len(item: &impl Writable) -> usize {
+    let mut buf = Vec::new();
+    item.write_to(&mut buf)
+        .expect("Writing into memory should not fail");
+    buf.len()
 }
 
 impl Writable for u8 {
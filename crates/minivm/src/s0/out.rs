@@ -160,3 +160,23 @@ impl Display for Constant {
         {index} {opcode} {operands}
         ...
 */
+
+impl Display for SizeReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, ".sections:")?;
+        writeln!(f, "header {}", self.header)?;
+        writeln!(f, "constants {}", self.constants)?;
+        writeln!(f, "start {}", self.start_code)?;
+        writeln!(f, "functions {}", self.function_table)?;
+        writeln!(f, "total {}", self.total())?;
+        writeln!(f, ".functions:")?;
+        for (name, size) in &self.functions {
+            writeln!(f, "{} {}", name, size)?;
+        }
+        writeln!(f, ".largest:")?;
+        for (name, size) in self.largest(Self::TOP_N) {
+            writeln!(f, "{} {}", name, size)?;
+        }
+        Ok(())
+    }
+}
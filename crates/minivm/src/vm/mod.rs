@@ -1,153 +1,652 @@
-//! Should be the virtual machine for c0 code.
+//! The virtual machine for o0 code.
 //!
-//! This thing is mostly unimplemented and I dont want to deal with it for now.
+//! The stack is made of 4-byte slots. A `double` occupies two slots, the
+//! high half first. Every frame starts with the parameters of its function,
+//! followed by whatever `snew` allocates.
+//!
+//! Execution starts with the start code as the frame of level 0, which then
+//! calls `main`. The program ends when `main` returns. `run_start` runs the
+//! start code alone, which leaves the global variables on the stack, and
+//! `enter_main` with `step` runs `main` one instruction at a time.
 
-#![allow(unreachable_patterns)]
-#![allow(unused_variables)]
 use crate::*;
+use std::fmt;
+use std::io::{BufRead, Write};
+
+/*
+    Address space:
+        Stack address:
+            00[slot_index:30]
+        Constant address:
+            01[constant_index:16][ptr_offset:14]
+*/
+const CONST_TAG: u32 = 0x4000_0000;
+const TAG_MASK: u32 = 0xc000_0000;
+
+/// Most frames that may be live at once, including the start code
+pub const MAX_CALL_DEPTH: usize = 1 << 16;
+/// Most slots `snew` may grow the stack to, 64 MiB
+pub const MAX_STACK_SLOTS: usize = 1 << 24;
 
-pub struct CallStack<'a> {
-    pub stack: Vec<u32>,
-    pub ip: u16,
-    pub f: &'a Vec<Inst>,
+#[derive(Debug)]
+pub enum VmError {
+    StackUnderflow,
+    /// Calls nested deeper than `MAX_CALL_DEPTH`, or locals beyond
+    /// `MAX_STACK_SLOTS`
+    StackOverflow,
+    BadAddress(u32),
+    BadConstant(u16),
+    BadFunction(u16),
+    NoMain,
+    DivideByZero,
+    /// Control reached the end of a function without returning
+    MissingReturn,
+    Unsupported(Inst),
+    BadInput(String),
+    Io(std::io::Error),
+    /// Ran more instructions than the VM was given with `with_fuel`
+    OutOfFuel,
+    /// `print` or `scan` in a VM made `without_io`
+    SideEffect(Inst),
 }
 
-impl<'a> CallStack<'a> {
-    pub fn of(f: &'a Vec<Inst>) -> CallStack<'a> {
-        CallStack {
-            f,
-            ip: 0,
-            stack: Vec::new(),
+impl fmt::Display for VmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VmError::StackUnderflow => write!(f, "Stack underflow"),
+            VmError::StackOverflow => write!(f, "Stack overflow"),
+            VmError::BadAddress(a) => write!(f, "Bad address {:#010x}", a),
+            VmError::BadConstant(c) => write!(f, "Bad constant index {}", c),
+            VmError::BadFunction(i) => write!(f, "Bad function index {}", i),
+            VmError::NoMain => write!(f, "No function named main"),
+            VmError::DivideByZero => write!(f, "Integer division by zero"),
+            VmError::MissingReturn => write!(f, "Function ended without returning"),
+            VmError::Unsupported(i) => write!(f, "Instruction {} is not supported", i),
+            VmError::BadInput(s) => write!(f, "Bad input: {}", s),
+            VmError::Io(e) => write!(f, "{}", e),
+            VmError::OutOfFuel => write!(f, "Ran out of fuel"),
+            VmError::SideEffect(i) => write!(f, "Instruction {} has side effects", i),
         }
     }
 }
 
-pub struct MiniVM<'a> {
-    pub prog: &'a O0,
-    pub call_stack: Vec<CallStack<'a>>,
+impl std::error::Error for VmError {}
+
+impl From<std::io::Error> for VmError {
+    fn from(e: std::io::Error) -> Self {
+        VmError::Io(e)
+    }
+}
+
+pub type VmResult<T> = Result<T, VmError>;
+
+struct Frame<'a> {
+    ins: &'a [Inst],
+    ip: usize,
+    /// Stack index of the first slot of this frame
+    bp: usize,
+    lvl: u16,
+    /// Index of the function, or `None` for the start code
+    func: Option<u16>,
+}
+
+pub struct MiniVM<'a, R, W>
+where
+    R: BufRead,
+    W: Write,
+{
+    prog: &'a O0,
+    stack: Vec<u32>,
+    frames: Vec<Frame<'a>>,
+    input: R,
+    output: W,
+    fuel: Option<u64>,
+    allow_io: bool,
+    /// Stack height right before `main` was called
+    main_base: usize,
 }
 
-impl<'a> MiniVM<'a> {
-    pub fn of(prog: &'a O0) -> MiniVM<'a> {
+impl<'a, R, W> MiniVM<'a, R, W>
+where
+    R: BufRead,
+    W: Write,
+{
+    /// A VM running `prog`, with `scan` reading `input` and `print` writing `output`
+    pub fn new(prog: &'a O0, input: R, output: W) -> MiniVM<'a, R, W> {
         MiniVM {
             prog,
-            call_stack: Vec::new(),
-        }
-    }
-
-    /*
-        Address space:
-            Stack address:
-                00[stack_index:16][ptr_offset:14]
-            Constant address:
-                01[constant_index:16][ptr_offset:14]
-            Heap address:
-                1[heap_index:16][ptr_offset:15]
-    */
-
-    pub fn run(&mut self) {
-        self.call_stack
-            .push(CallStack::of(&self.prog.start_code.ins))
-    }
-
-    fn run_f(&mut self) {
-        let cur_f = self.call_stack.last_mut().unwrap();
-        loop {
-            let inst = *cur_f
-                .f
-                .get(cur_f.ip as usize)
-                .expect("Instruction pointer overflow!");
-            cur_f.ip += 1;
-            match inst {
-                Inst::Nop => {}
-                Inst::CPush(a) => cur_f.stack.push(a as u32),
-                Inst::IPush(a) => cur_f.stack.push(a as u32),
-                Inst::Pop1 => {
-                    cur_f.stack.pop();
-                }
-                Inst::Pop2 => cur_f.stack.truncate(cur_f.stack.len() - 2),
-                Inst::PopN(a) => cur_f.stack.truncate(cur_f.stack.len() - a as usize),
-                Inst::Dup => cur_f
-                    .stack
-                    .push(*cur_f.stack.last().expect("Stack is empty")),
-                Inst::Dup2 => {
-                    let last = *cur_f.stack.last().expect("Stack is empty");
-                    let second_last = *cur_f
-                        .stack
-                        .get(cur_f.stack.len() - 2)
-                        .expect("Stack is empty");
-                    cur_f.stack.push(second_last);
-                    cur_f.stack.push(last);
+            stack: Vec::new(),
+            frames: Vec::new(),
+            input,
+            output,
+            fuel: None,
+            allow_io: true,
+            main_base: 0,
+        }
+    }
+
+    /// Stop with `VmError::OutOfFuel` once `fuel` instructions have run
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = Some(fuel);
+        self
+    }
+
+    /// Stop with `VmError::SideEffect` instead of running `print` or `scan`
+    pub fn without_io(mut self) -> Self {
+        self.allow_io = false;
+        self
+    }
+
+    /// Run the program to the end. Returns the value returned by `main`, or
+    /// 0 if it does not return one.
+    pub fn run(&mut self) -> VmResult<i32> {
+        let res = self.run_main();
+        self.output.flush()?;
+        res
+    }
+
+    /// Run the start code only. Returns the slots it leaves on the stack,
+    /// which hold the global variables.
+    pub fn run_start(&mut self) -> VmResult<&[u32]> {
+        self.frames.push(Frame {
+            ins: &self.prog.start_code.ins,
+            ip: 0,
+            bp: 0,
+            lvl: 0,
+            func: None,
+        });
+        self.run_frame()?;
+        Ok(&self.stack)
+    }
+
+    /// Run the start code and stop before the first instruction of `main`.
+    /// The program then runs one instruction at a time with `step`.
+    pub fn enter_main(&mut self) -> VmResult<()> {
+        let main = self
+            .prog
+            .functions
+            .iter()
+            .position(|f| match self.prog.constants.get(f.name_idx as usize) {
+                Some(Constant::String(s)) => s == b"main",
+                _ => false,
+            })
+            .ok_or(VmError::NoMain)?;
+
+        self.run_start()?;
+
+        self.main_base = self.stack.len();
+        self.call(main as u16)
+    }
+
+    /// Run one instruction after `enter_main`. Once `main` returns, this
+    /// gives what `run` would, and must not be called again.
+    pub fn step(&mut self) -> VmResult<Option<i32>> {
+        self.exec_next()?;
+        if self.frames.len() > 1 {
+            Ok(None)
+        } else {
+            self.output.flush()?;
+            self.main_result().map(Some)
+        }
+    }
+
+    /// The function and instruction index of the innermost frame, or `None`
+    /// in the start code
+    pub fn position(&self) -> Option<(u16, usize)> {
+        let frame = self.frames.last()?;
+        frame.func.map(|idx| (idx, frame.ip))
+    }
+
+    /// The function and instruction index of each frame, outermost first,
+    /// leaving out the start code. All frames but the innermost are just
+    /// past their `call`.
+    pub fn call_stack(&self) -> Vec<(u16, usize)> {
+        self.frames
+            .iter()
+            .filter_map(|frame| frame.func.map(|idx| (idx, frame.ip)))
+            .collect()
+    }
+
+    /// Names of the functions currently running, outermost first. After an
+    /// error this is the chain of calls that led to it.
+    pub fn call_chain(&self) -> Vec<&'a str> {
+        self.call_stack()
+            .into_iter()
+            .map(|(idx, _)| self.function_name(idx))
+            .collect()
+    }
+
+    /// Name of the function at `idx` in the function table
+    pub fn function_name(&self, idx: u16) -> &'a str {
+        let prog = self.prog;
+        let name = prog.functions.get(idx as usize).map(|f| f.name_idx);
+        match name.and_then(|name| prog.constants.get(name as usize)) {
+            Some(Constant::String(s)) => std::str::from_utf8(s).unwrap_or("?"),
+            _ => "?",
+        }
+    }
+
+    /// Slot `off` of the innermost frame, if it exists yet
+    pub fn local(&self, off: usize) -> Option<u32> {
+        let frame = self.frames.last()?;
+        self.stack.get(frame.bp + off).copied()
+    }
+
+    /// Slot `off` of the start code's frame, which holds the globals
+    pub fn global(&self, off: usize) -> Option<u32> {
+        self.stack.get(off).copied()
+    }
+
+    /// Write out whatever the program printed so far
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        self.output.flush()
+    }
+
+    fn run_main(&mut self) -> VmResult<i32> {
+        self.enter_main()?;
+        self.run_frame()?;
+        self.main_result()
+    }
+
+    /// What `main` left on the stack when it returned
+    fn main_result(&mut self) -> VmResult<i32> {
+        if self.stack.len() > self.main_base {
+            Ok(self.pop()? as i32)
+        } else {
+            Ok(0)
+        }
+    }
+
+    /// Run until the current frame returns, or the start code ends
+    fn run_frame(&mut self) -> VmResult<()> {
+        let depth = self.frames.len();
+        while self.frames.len() >= depth && self.exec_next()? {}
+        Ok(())
+    }
+
+    /// Run the next instruction of the current frame. Returns `false`
+    /// instead if it is the start code and that has ended.
+    fn exec_next(&mut self) -> VmResult<bool> {
+        let frame = self.frames.last_mut().unwrap();
+        let inst = match frame.ins.get(frame.ip) {
+            Some(inst) => *inst,
+            None if frame.lvl == 0 => return Ok(false),
+            None => return Err(VmError::MissingReturn),
+        };
+        frame.ip += 1;
+        if let Some(fuel) = &mut self.fuel {
+            *fuel = fuel.checked_sub(1).ok_or(VmError::OutOfFuel)?;
+        }
+        self.exec(inst)?;
+        Ok(true)
+    }
+
+    fn exec(&mut self, inst: Inst) -> VmResult<()> {
+        use Inst::*;
+        if !self.allow_io {
+            if let IPrint | DPrint | CPrint | SPrint | PrintLn | IScan | DScan | CScan = inst {
+                return Err(VmError::SideEffect(inst));
+            }
+        }
+        match inst {
+            Nop => {}
+            CPush(c) => self.push(c as u32),
+            IPush(i) => self.push(i as u32),
+            Pop1 => self.pop_n(1)?,
+            Pop2 => self.pop_n(2)?,
+            PopN(n) => self.pop_n(n as usize)?,
+            Dup => {
+                let v = self.peek(0)?;
+                self.push(v);
+            }
+            Dup2 => {
+                let hi = self.peek(1)?;
+                let lo = self.peek(0)?;
+                self.push(hi);
+                self.push(lo);
+            }
+
+            LoadC(idx) => match self.prog.constants.get(idx as usize) {
+                Some(Constant::Number(n)) => self.push(*n),
+                Some(Constant::Float(f)) => self.push_f64(*f),
+                Some(Constant::String(_)) => self.push(CONST_TAG | (idx as u32) << 14),
+                None => return Err(VmError::BadConstant(idx)),
+            },
+            LoadA(lvl_diff, off) => {
+                let cur = self.frames.last().unwrap().lvl;
+                let lvl = cur.checked_sub(lvl_diff).ok_or(VmError::StackUnderflow)?;
+                let frame = self
+                    .frames
+                    .iter()
+                    .rev()
+                    .find(|f| f.lvl == lvl)
+                    .ok_or(VmError::StackUnderflow)?;
+                self.push((frame.bp as i64 + off as i64) as u32);
+            }
+            SNew(n) => {
+                let len = self.stack.len() + n as usize;
+                if len > MAX_STACK_SLOTS {
+                    return Err(VmError::StackOverflow);
                 }
-                Inst::LoadC(a) => {
-                    let const_entry = self
-                        .prog
-                        .constants
-                        .get(a as usize)
-                        .expect("Bad constant entry");
-                    match const_entry {
-                        Constant::Float(f) => {
-                            let f = f.to_bits();
-                            cur_f.stack.push((f & 0xfffffff) as u32);
-                            cur_f.stack.push(((f & (0xfffffff << 8)) >> 8) as u32);
-                        }
-                        Constant::Number(n) => cur_f.stack.push(*n),
-                        Constant::String(s) => todo!(),
-                    }
+                self.stack.resize(len, 0);
+            }
+
+            ILoad | ALoad => {
+                let addr = self.pop()?;
+                let v = self.load(addr)?;
+                self.push(v);
+            }
+            DLoad => {
+                let addr = self.pop()?;
+                let hi = self.load(addr)?;
+                let lo = self.load(addr.wrapping_add(1))?;
+                self.push(hi);
+                self.push(lo);
+            }
+            IALoad | AALoad => {
+                let off = self.pop()?;
+                let addr = self.pop()?.wrapping_add(off);
+                let v = self.load(addr)?;
+                self.push(v);
+            }
+            DALoad => {
+                let off = self.pop()?;
+                let addr = self.pop()?.wrapping_add(off.wrapping_mul(2));
+                let hi = self.load(addr)?;
+                let lo = self.load(addr.wrapping_add(1))?;
+                self.push(hi);
+                self.push(lo);
+            }
+            IStore | AStore => {
+                let v = self.pop()?;
+                let addr = self.pop()?;
+                self.store(addr, v)?;
+            }
+            DStore => {
+                let lo = self.pop()?;
+                let hi = self.pop()?;
+                let addr = self.pop()?;
+                self.store(addr, hi)?;
+                self.store(addr.wrapping_add(1), lo)?;
+            }
+            IAStore | AAStore => {
+                let v = self.pop()?;
+                let off = self.pop()?;
+                let addr = self.pop()?.wrapping_add(off);
+                self.store(addr, v)?;
+            }
+            DAStore => {
+                let lo = self.pop()?;
+                let hi = self.pop()?;
+                let off = self.pop()?;
+                let addr = self.pop()?.wrapping_add(off.wrapping_mul(2));
+                self.store(addr, hi)?;
+                self.store(addr.wrapping_add(1), lo)?;
+            }
+
+            IAdd => self.int_op(|a, b| Ok(a.wrapping_add(b)))?,
+            ISub => self.int_op(|a, b| Ok(a.wrapping_sub(b)))?,
+            IMul => self.int_op(|a, b| Ok(a.wrapping_mul(b)))?,
+            IDiv => self.int_op(|a, b| {
+                if b == 0 {
+                    Err(VmError::DivideByZero)
+                } else {
+                    Ok(a.wrapping_div(b))
                 }
-                _ => todo!(),
-                Inst::LoadA(a, b) => {}
-                Inst::New => {}
-                Inst::SNew(a) => {}
-                Inst::ILoad => {}
-                Inst::DLoad => {}
-                Inst::ALoad => {}
-                Inst::IALoad => {}
-                Inst::DALoad => {}
-                Inst::AALoad => {}
-                Inst::IStore => {}
-                Inst::DStore => {}
-                Inst::AStore => {}
-                Inst::IAStore => {}
-                Inst::DAStore => {}
-                Inst::AAStore => {}
-                Inst::IAdd => {}
-                Inst::DAdd => {}
-                Inst::ISub => {}
-                Inst::DSub => {}
-                Inst::IMul => {}
-                Inst::DMul => {}
-                Inst::IDiv => {}
-                Inst::DDiv => {}
-                Inst::INeg => {}
-                Inst::DNeg => {}
-                Inst::ICmp => {}
-                Inst::DCmp => {}
-                Inst::I2D => {}
-                Inst::D2I => {}
-                Inst::I2C => {}
-                Inst::Jmp(a) => {}
-                Inst::JE(a) => {}
-                Inst::JNe(a) => {}
-                Inst::JL(a) => {}
-                Inst::JGe(a) => {}
-                Inst::JG(a) => {}
-                Inst::JLe(a) => {}
-                Inst::Call(a) => {}
-                Inst::Ret => {}
-                Inst::IRet => {}
-                Inst::DRet => {}
-                Inst::ARet => {}
-                Inst::IPrint => {}
-                Inst::DPrint => {}
-                Inst::CPrint => {}
-                Inst::SPrint => {}
-                Inst::PrintLn => {}
-                Inst::IScan => {}
-                Inst::DScan => {}
-                Inst::CScan => {}
-                _ => {}
+            })?,
+            ICmp => self.int_op(|a, b| Ok(a.cmp(&b) as i32))?,
+            INeg => {
+                let a = self.pop()? as i32;
+                self.push(a.wrapping_neg() as u32);
+            }
+            DAdd => self.double_op(|a, b| a + b)?,
+            DSub => self.double_op(|a, b| a - b)?,
+            DMul => self.double_op(|a, b| a * b)?,
+            DDiv => self.double_op(|a, b| a / b)?,
+            DNeg => {
+                let a = self.pop_f64()?;
+                self.push_f64(-a);
+            }
+            DCmp => {
+                let b = self.pop_f64()?;
+                let a = self.pop_f64()?;
+                let ord = a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal);
+                self.push(ord as i32 as u32);
+            }
+            I2D => {
+                let a = self.pop()? as i32;
+                self.push_f64(a as f64);
+            }
+            D2I => {
+                let a = self.pop_f64()?;
+                self.push(a as i32 as u32);
+            }
+            I2C => {
+                let a = self.pop()?;
+                self.push(a & 0xff);
+            }
+
+            Jmp(t) => self.jump(t),
+            JE(t) => self.jump_if(t, |v| v == 0)?,
+            JNe(t) => self.jump_if(t, |v| v != 0)?,
+            JL(t) => self.jump_if(t, |v| v < 0)?,
+            JGe(t) => self.jump_if(t, |v| v >= 0)?,
+            JG(t) => self.jump_if(t, |v| v > 0)?,
+            JLe(t) => self.jump_if(t, |v| v <= 0)?,
+
+            Call(idx) => self.call(idx)?,
+            Ret => self.ret(0)?,
+            IRet | ARet => self.ret(1)?,
+            DRet => self.ret(2)?,
+
+            IPrint => {
+                let v = self.pop()? as i32;
+                write!(self.output, "{}", v)?;
+            }
+            DPrint => {
+                let v = self.pop_f64()?;
+                write!(self.output, "{:.6}", v)?;
+            }
+            CPrint => {
+                let v = self.pop()?;
+                self.output.write_all(&[v as u8])?;
+            }
+            SPrint => {
+                let addr = self.pop()?;
+                let s = self.string_at(addr)?;
+                self.output.write_all(s)?;
+            }
+            PrintLn => writeln!(self.output)?,
+            IScan => {
+                let tok = self.scan_token()?;
+                let v: i32 = tok.parse().map_err(|_| VmError::BadInput(tok))?;
+                self.push(v as u32);
+            }
+            DScan => {
+                let tok = self.scan_token()?;
+                let v: f64 = tok.parse().map_err(|_| VmError::BadInput(tok))?;
+                self.push_f64(v);
+            }
+            CScan => {
+                let c = self
+                    .scan_byte()?
+                    .ok_or_else(|| VmError::BadInput("EOF".into()))?;
+                self.push(c as u32);
+            }
+
+            New | _Gt | _Lt | _Eq | _Gte | _Lte | _Neq => return Err(VmError::Unsupported(inst)),
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, idx: u16) -> VmResult<()> {
+        let f = self
+            .prog
+            .functions
+            .get(idx as usize)
+            .ok_or(VmError::BadFunction(idx))?;
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(VmError::StackOverflow);
+        }
+        let bp = self
+            .stack
+            .len()
+            .checked_sub(f.param_siz as usize)
+            .ok_or(VmError::StackUnderflow)?;
+        self.frames.push(Frame {
+            ins: &f.ins,
+            ip: 0,
+            bp,
+            lvl: f.lvl,
+            func: Some(idx),
+        });
+        Ok(())
+    }
+
+    /// Leave the current frame, keeping the top `slots` slots as return value
+    fn ret(&mut self, slots: usize) -> VmResult<()> {
+        let frame = self.frames.pop().unwrap();
+        let val_start = self
+            .stack
+            .len()
+            .checked_sub(slots)
+            .ok_or(VmError::StackUnderflow)?;
+        if val_start < frame.bp {
+            return Err(VmError::StackUnderflow);
+        }
+        self.stack.drain(frame.bp..val_start);
+        Ok(())
+    }
+
+    fn jump(&mut self, target: u16) {
+        self.frames.last_mut().unwrap().ip = target as usize;
+    }
+
+    fn jump_if(&mut self, target: u16, cond: impl Fn(i32) -> bool) -> VmResult<()> {
+        if cond(self.pop()? as i32) {
+            self.jump(target);
+        }
+        Ok(())
+    }
+
+    fn int_op(&mut self, op: impl Fn(i32, i32) -> VmResult<i32>) -> VmResult<()> {
+        let b = self.pop()? as i32;
+        let a = self.pop()? as i32;
+        self.push(op(a, b)? as u32);
+        Ok(())
+    }
+
+    fn double_op(&mut self, op: impl Fn(f64, f64) -> f64) -> VmResult<()> {
+        let b = self.pop_f64()?;
+        let a = self.pop_f64()?;
+        self.push_f64(op(a, b));
+        Ok(())
+    }
+
+    fn push(&mut self, v: u32) {
+        self.stack.push(v);
+    }
+
+    fn pop(&mut self) -> VmResult<u32> {
+        self.stack.pop().ok_or(VmError::StackUnderflow)
+    }
+
+    fn pop_n(&mut self, n: usize) -> VmResult<()> {
+        let len = self
+            .stack
+            .len()
+            .checked_sub(n)
+            .ok_or(VmError::StackUnderflow)?;
+        self.stack.truncate(len);
+        Ok(())
+    }
+
+    /// The slot `depth` slots below the top of the stack
+    fn peek(&self, depth: usize) -> VmResult<u32> {
+        self.stack
+            .len()
+            .checked_sub(depth + 1)
+            .map(|i| self.stack[i])
+            .ok_or(VmError::StackUnderflow)
+    }
+
+    fn push_f64(&mut self, v: f64) {
+        let bits = v.to_bits();
+        self.push((bits >> 32) as u32);
+        self.push(bits as u32);
+    }
+
+    fn pop_f64(&mut self) -> VmResult<f64> {
+        let lo = self.pop()? as u64;
+        let hi = self.pop()? as u64;
+        Ok(f64::from_bits(hi << 32 | lo))
+    }
+
+    fn load(&self, addr: u32) -> VmResult<u32> {
+        if addr & TAG_MASK != 0 {
+            return Err(VmError::BadAddress(addr));
+        }
+        self.stack
+            .get(addr as usize)
+            .copied()
+            .ok_or(VmError::BadAddress(addr))
+    }
+
+    fn store(&mut self, addr: u32, v: u32) -> VmResult<()> {
+        if addr & TAG_MASK != 0 {
+            return Err(VmError::BadAddress(addr));
+        }
+        let slot = self
+            .stack
+            .get_mut(addr as usize)
+            .ok_or(VmError::BadAddress(addr))?;
+        *slot = v;
+        Ok(())
+    }
+
+    /// The bytes of the string constant at `addr`, up to its end or a `\0`
+    fn string_at(&self, addr: u32) -> VmResult<&'a [u8]> {
+        if addr & TAG_MASK != CONST_TAG {
+            return Err(VmError::BadAddress(addr));
+        }
+        let idx = ((addr >> 14) & 0xffff) as u16;
+        let off = (addr & 0x3fff) as usize;
+        match self.prog.constants.get(idx as usize) {
+            Some(Constant::String(s)) if off <= s.len() => {
+                let s = &s[off..];
+                Ok(&s[..s.iter().position(|b| *b == 0).unwrap_or(s.len())])
             }
+            _ => Err(VmError::BadAddress(addr)),
+        }
+    }
+
+    fn scan_byte(&mut self) -> VmResult<Option<u8>> {
+        let c = self.input.fill_buf()?.first().copied();
+        if c.is_some() {
+            self.input.consume(1);
+        }
+        Ok(c)
+    }
+
+    /// Read a whitespace-separated token
+    fn scan_token(&mut self) -> VmResult<String> {
+        let mut tok = Vec::new();
+        while let Some(c) = self.input.fill_buf()?.first().copied() {
+            if !c.is_ascii_whitespace() {
+                break;
+            }
+            self.input.consume(1);
+        }
+        while let Some(c) = self.input.fill_buf()?.first().copied() {
+            if c.is_ascii_whitespace() {
+                break;
+            }
+            tok.push(c);
+            self.input.consume(1);
+        }
+        if tok.is_empty() {
+            return Err(VmError::BadInput("EOF".into()));
         }
+        Ok(String::from_utf8_lossy(&tok).into_owned())
     }
 }
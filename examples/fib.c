@@ -0,0 +1,29 @@
+int fib(int n) {
+  if (n <= 1) {
+    return n;
+  } else {
+    return fib(n - 1) + fib(n - 2);
+  }
+}
+
+int main() {
+  int n, i = 0;
+  scan(n);
+  while (i < n) {
+    print(i, fib(i));
+    i = i + 1;
+  }
+  return 0;
+}
+
+//== stdin:
+// 7
+//== expect-stdout:
+// 0 0
+// 1 1
+// 2 1
+// 3 2
+// 4 3
+// 5 5
+// 6 8
+//== expect-exitcode: 0
@@ -0,0 +1,22 @@
+int main() {
+  int a11, a12, a21, a22;
+  int b11, b12, b21, b22;
+  scan(a11);
+  scan(a12);
+  scan(a21);
+  scan(a22);
+  scan(b11);
+  scan(b12);
+  scan(b21);
+  scan(b22);
+  print(a11 * b11 + a12 * b21, a11 * b12 + a12 * b22);
+  print(a21 * b11 + a22 * b21, a21 * b12 + a22 * b22);
+  return 0;
+}
+
+//== stdin:
+// 1 2 3 4 5 6 7 8
+//== expect-stdout:
+// 19 22
+// 43 50
+//== expect-exitcode: 0
@@ -0,0 +1,35 @@
+int is_prime(int n) {
+  int d = 2;
+  if (n < 2) {
+    return 0;
+  }
+  while (d * d <= n) {
+    if (n / d * d == n) {
+      return 0;
+    }
+    d = d + 1;
+  }
+  return 1;
+}
+
+int main() {
+  int n, i = 2;
+  scan(n);
+  while (i <= n) {
+    if (is_prime(i)) {
+      print(i);
+    }
+    i = i + 1;
+  }
+  return 0;
+}
+
+//== stdin:
+// 12
+//== expect-stdout:
+// 2
+// 3
+// 5
+// 7
+// 11
+//== expect-exitcode: 0
@@ -0,0 +1,21 @@
+void reverse() {
+  char c;
+  scan(c);
+  if (c != '\n') {
+    reverse();
+    print(c);
+  }
+}
+
+int main() {
+  reverse();
+  return 0;
+}
+
+//== stdin:
+// abc
+//== expect-stdout:
+// c
+// b
+// a
+//== expect-exitcode: 0
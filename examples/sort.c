@@ -0,0 +1,40 @@
+int main() {
+  int a, b, c, d, t;
+  scan(a);
+  scan(b);
+  scan(c);
+  scan(d);
+  if (a > b) {
+    t = a;
+    a = b;
+    b = t;
+  }
+  if (c > d) {
+    t = c;
+    c = d;
+    d = t;
+  }
+  if (a > c) {
+    t = a;
+    a = c;
+    c = t;
+  }
+  if (b > d) {
+    t = b;
+    b = d;
+    d = t;
+  }
+  if (b > c) {
+    t = b;
+    b = c;
+    c = t;
+  }
+  print(a, b, c, d);
+  return 0;
+}
+
+//== stdin:
+// 3 -1 7 2
+//== expect-stdout:
+// -1 2 3 7
+//== expect-exitcode: 0
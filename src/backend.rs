@@ -0,0 +1,172 @@
+use crate::c0::ast::Program;
+use crate::prelude::*;
+use indexmap::IndexMap;
+use std::fmt;
+
+/// DOT control flow graph of every function, keyed by function name
+pub type Cfgs = IndexMap<String, String>;
+
+/// The result of compiling a program with a backend
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Artifact {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+impl Artifact {
+    pub fn write_to(&self, w: &mut impl std::io::Write) -> std::io::Result<()> {
+        match self {
+            Artifact::Text(s) => w.write_all(s.as_bytes()),
+            Artifact::Binary(b) => w.write_all(b),
+        }
+    }
+}
+
+/// A compile error reported by any backend.
+///
+/// > When span is not avaliable, it is `None`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BackendError {
+    pub msg: String,
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for BackendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.msg)
+    }
+}
+
+pub trait Backend {
+    /// Name used to select this backend on the command line
+    fn name(&self) -> &'static str;
+
+    /// Whether the artifact can be written to stdout
+    fn is_text(&self) -> bool;
+
+    fn compile(&self, prog: &Program) -> Result<Artifact, BackendError>;
+
+    /// Like `compile`, but also returns the control flow graphs of the same
+    /// compilation. `None` if the backend has no basic blocks to show.
+    fn compile_with_cfg(&self, prog: &Program) -> Result<(Artifact, Option<Cfgs>), BackendError> {
+        self.compile(prog).map(|a| (a, None))
+    }
+}
+
+/// Backend used when `--emit` is not given on the command line
+pub const DEFAULT_EMIT: &str = "o0";
+
+/// Every backend known to the compiler. Register new backends here.
+pub fn backends() -> Vec<Box<dyn Backend>> {
+    vec![
+        Box::new(S0Backend),
+        Box::new(O0Backend),
+        Box::new(C89Backend),
+    ]
+}
+
+/// Find the backend registered as `name`
+pub fn find_backend(name: &str) -> Option<Box<dyn Backend>> {
+    backends().into_iter().find(|b| b.name() == name)
+}
+
+fn minivm_compile(prog: &Program) -> Result<crate::minivm::O0, BackendError> {
+    crate::minivm::Codegen::new(prog)
+        .compile()
+        .map_err(minivm_error)
+}
+
+fn minivm_compile_with_cfg(prog: &Program) -> Result<(crate::minivm::O0, Cfgs), BackendError> {
+    crate::minivm::Codegen::new(prog)
+        .compile_with_cfg()
+        .map_err(minivm_error)
+}
+
+fn minivm_error(e: crate::minivm::CompileError) -> BackendError {
+    BackendError {
+        msg: format!("{}", e.var),
+        span: e.span,
+    }
+}
+
+/// Run the checks that only happen during code generation, like argument
+/// counts and assigning `void`, and throw the generated code away
+pub fn check(prog: &Program) -> Result<(), BackendError> {
+    minivm_compile(prog).map(|_| ())
+}
+
+/// C0 assembly file of the stack VM
+pub struct S0Backend;
+
+impl Backend for S0Backend {
+    fn name(&self) -> &'static str {
+        "s0"
+    }
+
+    fn is_text(&self) -> bool {
+        true
+    }
+
+    fn compile(&self, prog: &Program) -> Result<Artifact, BackendError> {
+        minivm_compile(prog).map(|o0| Artifact::Text(format!("{}", o0)))
+    }
+
+    fn compile_with_cfg(&self, prog: &Program) -> Result<(Artifact, Option<Cfgs>), BackendError> {
+        let (o0, cfgs) = minivm_compile_with_cfg(prog)?;
+        Ok((Artifact::Text(format!("{}", o0)), Some(cfgs)))
+    }
+}
+
+/// C0 binary file of the stack VM
+pub struct O0Backend;
+
+impl Backend for O0Backend {
+    fn name(&self) -> &'static str {
+        "o0"
+    }
+
+    fn is_text(&self) -> bool {
+        false
+    }
+
+    fn compile(&self, prog: &Program) -> Result<Artifact, BackendError> {
+        o0_binary(&minivm_compile(prog)?)
+    }
+
+    fn compile_with_cfg(&self, prog: &Program) -> Result<(Artifact, Option<Cfgs>), BackendError> {
+        let (o0, cfgs) = minivm_compile_with_cfg(prog)?;
+        Ok((o0_binary(&o0)?, Some(cfgs)))
+    }
+}
+
+fn o0_binary(o0: &crate::minivm::O0) -> Result<Artifact, BackendError> {
+    let mut buf = Vec::new();
+    o0.write_binary(&mut buf).map_err(|e| BackendError {
+        msg: format!("{}", e),
+        span: None,
+    })?;
+    Ok(Artifact::Binary(buf))
+}
+
+/// C89 source
+pub struct C89Backend;
+
+impl Backend for C89Backend {
+    fn name(&self) -> &'static str {
+        "c"
+    }
+
+    fn is_text(&self) -> bool {
+        true
+    }
+
+    fn compile(&self, prog: &Program) -> Result<Artifact, BackendError> {
+        crate::c89::Codegen::new(prog)
+            .compile()
+            .map(Artifact::Text)
+            .map_err(|e| BackendError {
+                msg: format!("{}", e),
+                span: None,
+            })
+    }
+}
@@ -0,0 +1,61 @@
+use crate::backend::DEFAULT_EMIT;
+use std::fmt;
+
+/// What this build of the compiler contains
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BuildInfo {
+    pub version: &'static str,
+    /// Names of the registered backends, as accepted by `--emit`
+    pub backends: Vec<&'static str>,
+    /// Passes run by the minivm backend, in order
+    pub passes: Vec<&'static str>,
+    /// Rules of the minivm peephole pass, in order
+    pub peephole_rules: Vec<&'static str>,
+    /// Cargo features enabled at build time
+    pub features: Vec<&'static str>,
+    pub default_emit: &'static str,
+}
+
+/// Describe this build of the compiler
+pub fn build_info() -> BuildInfo {
+    let mut features = Vec::new();
+    if cfg!(feature = "cranelift_codegen") {
+        features.push("cranelift_codegen");
+    }
+
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        backends: crate::backend::backends()
+            .iter()
+            .map(|b| b.name())
+            .collect(),
+        passes: crate::minivm::codegen::PASSES
+            .iter()
+            .map(|p| p.name)
+            .collect(),
+        peephole_rules: crate::minivm::peephole::RULES
+            .iter()
+            .map(|r| r.name)
+            .collect(),
+        features,
+        default_emit: DEFAULT_EMIT,
+    }
+}
+
+impl fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |items: &[&str]| {
+            if items.is_empty() {
+                "(none)".to_owned()
+            } else {
+                items.join(", ")
+            }
+        };
+        writeln!(f, "chigusa {}", self.version)?;
+        writeln!(f, "backends: {}", list(&self.backends))?;
+        writeln!(f, "passes: {}", list(&self.passes))?;
+        writeln!(f, "peephole rules: {}", list(&self.peephole_rules))?;
+        writeln!(f, "features: {}", list(&self.features))?;
+        writeln!(f, "default emit: {}", self.default_emit)
+    }
+}
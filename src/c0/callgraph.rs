@@ -0,0 +1,325 @@
+use super::ast::*;
+use indexmap::{IndexMap, IndexSet};
+use std::fmt::{self, Write};
+
+/// Static call graph of a program, built from function bodies in the AST.
+///
+/// Functions are kept in declaration order, and so are the callees of each
+/// function.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct CallGraph {
+    pub calls: IndexMap<String, IndexSet<String>>,
+}
+
+impl CallGraph {
+    pub fn new(prog: &Program) -> CallGraph {
+        let mut calls = IndexMap::new();
+        let scope = prog.blk.scope.borrow();
+        for (name, def) in scope.defs.iter() {
+            if let SymbolDef::Var { typ, .. } = &*def.borrow() {
+                if let TypeDef::Function(f) = &*typ.borrow() {
+                    let mut callees = IndexSet::new();
+                    if let Some(body) = &f.body {
+                        for stmt in &body.stmts {
+                            stmt_calls(stmt, &mut callees);
+                        }
+                    }
+                    calls.insert(name.clone(), callees);
+                }
+            }
+        }
+        CallGraph { calls }
+    }
+
+    /// Strongly connected components of the graph, callees before callers.
+    pub fn sccs(&self) -> Vec<Vec<String>> {
+        Tarjan::new(self).run()
+    }
+
+    /// Is `name` part of a (possibly mutual) recursion?
+    ///
+    /// Computes the components every time; to ask about many functions use
+    /// [`recursive_sets`](Self::recursive_sets) once instead.
+    pub fn is_recursive(&self, name: &str) -> bool {
+        self.recursive_sets()
+            .iter()
+            .any(|s| s.iter().any(|f| f == name))
+    }
+
+    /// Components that actually recurse: more than one function, or one
+    /// function calling itself.
+    pub fn recursive_sets(&self) -> Vec<Vec<String>> {
+        self.sccs()
+            .into_iter()
+            .filter(|s| s.len() > 1 || self.calls[&s[0]].contains(&s[0]))
+            .collect()
+    }
+
+    /// The longest chain of calls starting from `root` in which no function
+    /// appears twice.
+    ///
+    /// Recursion is followed until it would reach a function already on the
+    /// chain; use [`is_recursive`](Self::is_recursive) to find out whether a
+    /// step of it can actually go deeper. Every step of the path is a call
+    /// made by the function before it.
+    pub fn max_depth_path(&self, root: &str) -> Option<Vec<String>> {
+        if !self.calls.contains_key(root) {
+            return None;
+        }
+        let sccs = self.sccs();
+        let comp_of: IndexMap<&str, usize> = sccs
+            .iter()
+            .enumerate()
+            .flat_map(|(i, s)| s.iter().map(move |f| (&f[..], i)))
+            .collect();
+
+        // * Components are in reverse topological order, so every callee in
+        //   another component is already resolved when we reach a component.
+        //   A chain that leaves a component never comes back to it, so only
+        //   paths inside the current component need to be searched.
+        let mut best: IndexMap<&str, Vec<&str>> = IndexMap::new();
+        for (i, comp) in sccs.iter().enumerate() {
+            let found: Vec<_> = comp
+                .iter()
+                .map(|f| {
+                    let mut longest = Vec::new();
+                    self.walk_component(i, &comp_of, &best, &mut vec![&f[..]], &mut longest);
+                    (&f[..], longest)
+                })
+                .collect();
+            best.extend(found);
+        }
+
+        Some(best[root].iter().map(|f| (*f).to_owned()).collect())
+    }
+
+    /// Try every path inside component `comp` that extends `path` without
+    /// repeating a function. Each one, followed by the longest chain out of
+    /// the component from its last function, replaces `longest` if it is
+    /// longer.
+    fn walk_component<'a>(
+        &'a self,
+        comp: usize,
+        comp_of: &IndexMap<&str, usize>,
+        best: &IndexMap<&'a str, Vec<&'a str>>,
+        path: &mut Vec<&'a str>,
+        longest: &mut Vec<&'a str>,
+    ) {
+        let f = *path.last().unwrap();
+
+        let mut exit: &[&str] = &[];
+        for callee in &self.calls[f] {
+            match comp_of.get(&callee[..]) {
+                Some(&c) if c != comp => {}
+                _ => continue,
+            }
+            let chain = &best[&callee[..]];
+            if chain.len() > exit.len() {
+                exit = chain;
+            }
+        }
+        if path.len() + exit.len() > longest.len() {
+            *longest = path.iter().chain(exit).cloned().collect();
+        }
+
+        for callee in &self.calls[f] {
+            if comp_of.get(&callee[..]) == Some(&comp) && !path.contains(&&callee[..]) {
+                path.push(callee);
+                self.walk_component(comp, comp_of, best, path, longest);
+                path.pop();
+            }
+        }
+    }
+
+    /// Render the graph in Graphviz DOT. Recursive functions are drawn red,
+    /// and the deepest call chain from `main` is drawn bold.
+    pub fn to_dot(&self) -> String {
+        let mut s = String::new();
+        self.write_dot(&mut s).unwrap();
+        s
+    }
+
+    fn write_dot(&self, s: &mut String) -> fmt::Result {
+        let recursive = self.recursive_sets();
+        let is_recursive = members(&recursive);
+        let deepest = self.max_depth_path("main").unwrap_or_default();
+        let on_path = |from: &str, to: &str| deepest.windows(2).any(|w| w[0] == from && w[1] == to);
+
+        writeln!(s, "digraph callgraph {{")?;
+        writeln!(s, "    node [shape=box];")?;
+        for (i, set) in recursive.iter().filter(|s| s.len() > 1).enumerate() {
+            writeln!(s, "    subgraph cluster_{} {{", i)?;
+            writeln!(s, "        style=dashed;")?;
+            for f in set {
+                writeln!(s, "        {:?};", f)?;
+            }
+            writeln!(s, "    }}")?;
+        }
+        for f in self.calls.keys() {
+            if is_recursive.contains(&f[..]) {
+                writeln!(s, "    {:?} [color=red];", f)?;
+            } else {
+                writeln!(s, "    {:?};", f)?;
+            }
+        }
+        for (f, callees) in &self.calls {
+            for callee in callees {
+                if on_path(f, callee) {
+                    writeln!(s, "    {:?} -> {:?} [style=bold];", f, callee)?;
+                } else {
+                    writeln!(s, "    {:?} -> {:?};", f, callee)?;
+                }
+            }
+        }
+        writeln!(s, "}}")
+    }
+}
+
+impl fmt::Display for CallGraph {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let recursive = self.recursive_sets();
+        let is_recursive = members(&recursive);
+        for (func, callees) in &self.calls {
+            write!(f, "{}", func)?;
+            if !callees.is_empty() {
+                let callees: Vec<_> = callees.iter().map(|s| &s[..]).collect();
+                write!(f, " -> {}", callees.join(", "))?;
+            }
+            if is_recursive.contains(&func[..]) {
+                write!(f, " [recursive]")?;
+            }
+            writeln!(f)?;
+        }
+        for set in &recursive {
+            writeln!(f, "recursion: {}", set.join(", "))?;
+        }
+        if let Some(path) = self.max_depth_path("main") {
+            writeln!(f, "max depth ({}): {}", path.len(), path.join(" -> "))?;
+        }
+        Ok(())
+    }
+}
+
+/// Every function of the given components
+fn members(sets: &[Vec<String>]) -> IndexSet<&str> {
+    sets.iter().flatten().map(|f| &f[..]).collect()
+}
+
+fn stmt_calls(stmt: &Stmt, calls: &mut IndexSet<String>) {
+    match &stmt.var {
+        StmtVariant::If(i) => {
+            expr_calls(&i.cond.borrow(), calls);
+            stmt_calls(&i.if_block.borrow(), calls);
+            if let Some(else_block) = &i.else_block {
+                stmt_calls(&else_block.borrow(), calls);
+            }
+        }
+        StmtVariant::While(w) => {
+            expr_calls(&w.cond.borrow(), calls);
+            stmt_calls(&w.block.borrow(), calls);
+        }
+        StmtVariant::Block(b) => {
+            for stmt in &b.stmts {
+                stmt_calls(stmt, calls);
+            }
+        }
+        StmtVariant::Expr(e) | StmtVariant::Return(Some(e)) => expr_calls(&e.borrow(), calls),
+        StmtVariant::ManyExpr(es) | StmtVariant::Print(es) => {
+            for e in es {
+                expr_calls(&e.borrow(), calls);
+            }
+        }
+        StmtVariant::Scan(_)
+        | StmtVariant::Return(None)
+        | StmtVariant::Break
+        | StmtVariant::Empty => {}
+    }
+}
+
+fn expr_calls(expr: &Expr, calls: &mut IndexSet<String>) {
+    match &expr.var {
+        ExprVariant::FunctionCall(f) => {
+            calls.insert(f.func.clone());
+            for p in &f.params {
+                expr_calls(&p.borrow(), calls);
+            }
+        }
+        ExprVariant::TypeConversion(t) => expr_calls(&t.expr.borrow(), calls),
+        ExprVariant::UnaryOp(u) => expr_calls(&u.val.borrow(), calls),
+        ExprVariant::BinaryOp(b) => {
+            expr_calls(&b.lhs.borrow(), calls);
+            expr_calls(&b.rhs.borrow(), calls);
+        }
+        ExprVariant::StructChild(s) => expr_calls(&s.val.borrow(), calls),
+        ExprVariant::ArrayChild(a) => {
+            expr_calls(&a.val.borrow(), calls);
+            expr_calls(&a.idx.borrow(), calls);
+        }
+        ExprVariant::Ident(_) | ExprVariant::Literal(_) => {}
+    }
+}
+
+/// Tarjan's strongly connected components algorithm
+struct Tarjan<'a> {
+    graph: &'a CallGraph,
+    index: IndexMap<&'a str, (usize, usize)>,
+    stack: Vec<&'a str>,
+    on_stack: IndexSet<&'a str>,
+    result: Vec<Vec<String>>,
+}
+
+impl<'a> Tarjan<'a> {
+    fn new(graph: &'a CallGraph) -> Tarjan<'a> {
+        Tarjan {
+            graph,
+            index: IndexMap::new(),
+            stack: Vec::new(),
+            on_stack: IndexSet::new(),
+            result: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Vec<Vec<String>> {
+        for f in self.graph.calls.keys() {
+            if !self.index.contains_key(&f[..]) {
+                self.visit(f);
+            }
+        }
+        self.result
+    }
+
+    fn visit(&mut self, f: &'a str) -> usize {
+        let idx = self.index.len();
+        self.index.insert(f, (idx, idx));
+        self.stack.push(f);
+        self.on_stack.insert(f);
+
+        let mut low = idx;
+        for callee in &self.graph.calls[f] {
+            if !self.graph.calls.contains_key(callee) {
+                continue;
+            }
+            let callee = &callee[..];
+            match self.index.get(callee) {
+                None => low = low.min(self.visit(callee)),
+                Some(&(i, _)) if self.on_stack.contains(callee) => low = low.min(i),
+                _ => {}
+            }
+        }
+        self.index[f].1 = low;
+
+        if low == idx {
+            let mut comp = Vec::new();
+            while let Some(g) = self.stack.pop() {
+                self.on_stack.remove(g);
+                comp.push(g.to_owned());
+                if g == f {
+                    break;
+                }
+            }
+            comp.reverse();
+            self.result.push(comp);
+        }
+        low
+    }
+}
@@ -0,0 +1,302 @@
+use super::ast::*;
+use crate::prelude::*;
+use std::fmt::{self, Write};
+
+/// Width of one indentation level
+const INDENT: usize = 2;
+
+/// Dump the program as an indented s-expression tree.
+///
+/// Every node carries its span as `@line:col-line:col` (1-based, end
+/// exclusive). Nothing that changes between runs, like scope ids, is
+/// included, so dumps of the same input can be diffed.
+pub fn dump(prog: &Program) -> String {
+    let mut d = Dumper {
+        out: String::new(),
+        depth: 0,
+    };
+    d.program(prog).unwrap();
+    d.out
+}
+
+struct Dumper {
+    out: String,
+    depth: usize,
+}
+
+impl Dumper {
+    fn program(&mut self, prog: &Program) -> fmt::Result {
+        self.open("program", None)?;
+        let scope = prog.blk.scope.borrow();
+        for (name, def) in scope.defs.iter() {
+            if let SymbolDef::Var {
+                typ,
+                is_const,
+                decl_span,
+            } = &*def.borrow()
+            {
+                match &*typ.borrow() {
+                    TypeDef::Function(f) => self.func(name, f, *decl_span)?,
+                    typ => self.var("global", name, typ, *is_const, Some(*decl_span))?,
+                }
+            }
+        }
+        for stmt in &prog.blk.stmts {
+            self.stmt(stmt)?;
+        }
+        self.close()
+    }
+
+    fn func(&mut self, name: &str, f: &FunctionType, span: Span) -> fmt::Result {
+        let head = format!("fn {} -> {}", name, type_name(&f.return_type.borrow()));
+        self.open(&head, Some(span))?;
+        match &f.body {
+            Some(body) => {
+                let scope = body.scope.borrow();
+                let params = scope.defs.iter().take(f.params.len());
+                for ((name, def), typ) in params.zip(f.params.iter()) {
+                    let span = match &*def.borrow() {
+                        SymbolDef::Var { decl_span, .. } => Some(*decl_span),
+                        _ => None,
+                    };
+                    self.var("param", name, &typ.borrow(), false, span)?;
+                }
+                drop(scope);
+                self.block(body, f.params.len())?;
+            }
+            None => {
+                for typ in &f.params {
+                    self.leaf(&format!("param {}", type_name(&typ.borrow())), None)?;
+                }
+            }
+        }
+        self.close()
+    }
+
+    fn var(
+        &mut self,
+        kind: &str,
+        name: &str,
+        typ: &TypeDef,
+        is_const: bool,
+        span: Option<Span>,
+    ) -> fmt::Result {
+        let qual = if is_const { "const " } else { "" };
+        self.leaf(
+            &format!("{} {}{} {}", kind, qual, type_name(typ), name),
+            span,
+        )
+    }
+
+    /// Dump a block, skipping the first `skip` declarations of its scope
+    fn block(&mut self, b: &Block, skip: usize) -> fmt::Result {
+        self.open("block", b.span)?;
+        for (name, def) in b.scope.borrow().defs.iter().skip(skip) {
+            if let SymbolDef::Var {
+                typ,
+                is_const,
+                decl_span,
+            } = &*def.borrow()
+            {
+                self.var("let", name, &typ.borrow(), *is_const, Some(*decl_span))?;
+            }
+        }
+        for stmt in &b.stmts {
+            self.stmt(stmt)?;
+        }
+        self.close()
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) -> fmt::Result {
+        let span = Some(stmt.span);
+        match &stmt.var {
+            StmtVariant::Block(b) => self.block(b, 0),
+            StmtVariant::If(i) => {
+                self.open("if", span)?;
+                self.expr(&i.cond.borrow())?;
+                self.stmt(&i.if_block.borrow())?;
+                if let Some(else_block) = &i.else_block {
+                    self.stmt(&else_block.borrow())?;
+                }
+                self.close()
+            }
+            StmtVariant::While(w) => {
+                self.open("while", span)?;
+                self.expr(&w.cond.borrow())?;
+                self.stmt(&w.block.borrow())?;
+                self.close()
+            }
+            StmtVariant::Expr(e) => {
+                self.open("expr", span)?;
+                self.expr(&e.borrow())?;
+                self.close()
+            }
+            // * Declarations without initializers leave an empty list behind
+            StmtVariant::ManyExpr(es) if es.is_empty() => Ok(()),
+            StmtVariant::ManyExpr(es) => self.exprs("exprs", es, span),
+            StmtVariant::Print(es) => self.exprs("print", es, span),
+            StmtVariant::Scan(i) => self.leaf(&format!("scan {}", i.name), span),
+            StmtVariant::Return(None) => self.leaf("return", span),
+            StmtVariant::Return(Some(e)) => self.exprs("return", std::slice::from_ref(e), span),
+            StmtVariant::Break => self.leaf("break", span),
+            StmtVariant::Empty => Ok(()),
+        }
+    }
+
+    fn exprs(&mut self, head: &str, es: &[Ptr<Expr>], span: Option<Span>) -> fmt::Result {
+        self.open(head, span)?;
+        for e in es {
+            self.expr(&e.borrow())?;
+        }
+        self.close()
+    }
+
+    fn expr(&mut self, e: &Expr) -> fmt::Result {
+        let span = Some(e.span);
+        match &e.var {
+            ExprVariant::Ident(i) => self.leaf(&format!("ident {}", i.name), span),
+            ExprVariant::Literal(lit) => self.leaf(&literal(lit), span),
+            ExprVariant::TypeConversion(t) => {
+                self.open(&format!("cast {}", type_name(&t.to.borrow())), span)?;
+                self.expr(&t.expr.borrow())?;
+                self.close()
+            }
+            ExprVariant::UnaryOp(u) => {
+                self.open(&format!("unary {}", op_name(u.op)), span)?;
+                self.expr(&u.val.borrow())?;
+                self.close()
+            }
+            ExprVariant::BinaryOp(b) => {
+                self.open(&format!("binary {}", op_name(b.op)), span)?;
+                self.expr(&b.lhs.borrow())?;
+                self.expr(&b.rhs.borrow())?;
+                self.close()
+            }
+            ExprVariant::FunctionCall(f) => {
+                self.open(&format!("call {}", f.func), span)?;
+                for p in &f.params {
+                    self.expr(&p.borrow())?;
+                }
+                self.close()
+            }
+            ExprVariant::StructChild(s) => {
+                self.open(&format!("field {}", s.idx), span)?;
+                self.expr(&s.val.borrow())?;
+                self.close()
+            }
+            ExprVariant::ArrayChild(a) => {
+                self.open("index", span)?;
+                self.expr(&a.val.borrow())?;
+                self.expr(&a.idx.borrow())?;
+                self.close()
+            }
+        }
+    }
+
+    /// Start a node with children on the following lines
+    fn open(&mut self, head: &str, span: Option<Span>) -> fmt::Result {
+        self.line(head, span)?;
+        self.depth += 1;
+        Ok(())
+    }
+
+    /// End the node opened last
+    fn close(&mut self) -> fmt::Result {
+        self.depth -= 1;
+        self.close_leaf()
+    }
+
+    /// A node without children
+    fn leaf(&mut self, head: &str, span: Option<Span>) -> fmt::Result {
+        self.line(head, span)?;
+        self.close_leaf()
+    }
+
+    /// Put the closing parenthesis at the end of the last line
+    fn close_leaf(&mut self) -> fmt::Result {
+        self.out.pop();
+        writeln!(self.out, ")")
+    }
+
+    fn line(&mut self, head: &str, span: Option<Span>) -> fmt::Result {
+        write!(
+            self.out,
+            "{:indent$}({}",
+            "",
+            head,
+            indent = self.depth * INDENT
+        )?;
+        if let Some(span) = span {
+            write!(
+                self.out,
+                " @{}:{}-{}:{}",
+                span.start.ln + 1,
+                span.start.pos + 1,
+                span.end.ln + 1,
+                span.end.pos + 1
+            )?;
+        }
+        writeln!(self.out)
+    }
+}
+
+fn type_name(typ: &TypeDef) -> String {
+    match typ {
+        TypeDef::NamedType(n) => n.clone(),
+        TypeDef::Unit => "void".into(),
+        TypeDef::Primitive(p) => match p.var {
+            PrimitiveTypeVar::Float => "double".into(),
+            PrimitiveTypeVar::UnsignedInt if p.occupy_bytes == 1 => "char".into(),
+            _ => "int".into(),
+        },
+        TypeDef::Ref(r) => format!("{}*", type_name(&r.target.borrow())),
+        TypeDef::Array(a) => match a.length {
+            Some(len) => format!("{}[{}]", type_name(&a.target.borrow()), len),
+            None => format!("{}[]", type_name(&a.target.borrow())),
+        },
+        TypeDef::Function(..) => "fn".into(),
+        other => format!("{:?}", other),
+    }
+}
+
+fn literal(lit: &Literal) -> String {
+    match lit {
+        Literal::Char { val } => format!("char {:?}", val),
+        Literal::Integer { val } => format!("int {}", val),
+        Literal::Float { val } => format!("double {:?}", val.to_f64()),
+        Literal::Boolean { val } => format!("bool {}", val),
+        Literal::String { val } => format!("str {:?}", val),
+        Literal::Struct { .. } => format!("struct {}", lit),
+    }
+}
+
+fn op_name(op: OpVar) -> &'static str {
+    use OpVar::*;
+    match op {
+        Add | Pos => "+",
+        Sub | Neg => "-",
+        Mul | Der => "*",
+        Div => "/",
+        And => "&&",
+        Or => "||",
+        Xor => "^",
+        Ban | Ref => "&",
+        Bor => "|",
+        Gt => ">",
+        Lt => "<",
+        Eq => "==",
+        Gte => ">=",
+        Lte => "<=",
+        Neq => "!=",
+        Inv => "!",
+        Bin => "~",
+        Ina => "x++",
+        Inb => "++x",
+        Dea => "x--",
+        Deb => "--x",
+        _Com => ",",
+        _Asn => "=",
+        _Csn => "const=",
+        _Lpr | _Rpr | _Dum => "?",
+    }
+}
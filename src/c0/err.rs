@@ -85,6 +85,7 @@ pub enum ParseErrVariant {
     UnsupportedToken(TokenType),
 
     DuplicateDeclaration(String),
+    BuiltinRedeclaration(String),
     BadIdentifier(String),
     ConflictingDeclaration(String),
     EarlyEof,
@@ -132,6 +133,9 @@ impl ParseErrVariant {
             ),
 
             DuplicateDeclaration(ident) => format!("Identifier '{}' is declared before", ident),
+            BuiltinRedeclaration(ident) => {
+                format!("'{}' is a builtin type and cannot be redeclared", ident)
+            }
             BadIdentifier(ident) => format!("Identifier '{}' is invalid", ident),
             ConflictingDeclaration(ident) => {
                 format!("Identifier '{}' has conflicting declarations", ident)
@@ -719,10 +719,13 @@ where
             loop {
                 let c = self.iter.next();
                 match c {
-                    Some((_, '\r')) | Some((_, '\n')) | Some((_, '\0')) => {
-                        self.iter.next();
+                    Some((_, '\r')) => {
+                        if let Some((_, '\n')) = self.iter.peek() {
+                            self.iter.next();
+                        }
                         break;
                     }
+                    Some((_, '\n')) | Some((_, '\0')) => break,
                     None => break,
                     Some((_, c)) => comment_data.push(c),
                 }
@@ -0,0 +1,64 @@
+use super::ast::*;
+use crate::diag::DiagSink;
+
+/// Run all lints over the program, reporting into `sink`
+pub fn lint(prog: &Program, sink: &DiagSink) {
+    let scope = prog.blk.scope.borrow();
+    for def in scope.defs.values() {
+        if let SymbolDef::Var { typ, .. } = &*def.borrow() {
+            if let TypeDef::Function(f) = &*typ.borrow() {
+                if let Some(body) = &f.body {
+                    for stmt in &body.stmts {
+                        lint_stmt(stmt, sink);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn lint_stmt(stmt: &Stmt, sink: &DiagSink) {
+    match &stmt.var {
+        // * Calls are the only expressions that may be void, and those are
+        // * never reported, so every expression reported here has a value.
+        StmtVariant::Expr(e) if !has_side_effect(&e.borrow()) => {
+            sink.warn(stmt.span, "Result of expression is unused");
+        }
+        StmtVariant::If(i) => {
+            lint_stmt(&i.if_block.borrow(), sink);
+            if let Some(else_block) = &i.else_block {
+                lint_stmt(&else_block.borrow(), sink);
+            }
+        }
+        StmtVariant::While(w) => lint_stmt(&w.block.borrow(), sink),
+        StmtVariant::Block(b) => {
+            for stmt in &b.stmts {
+                lint_stmt(stmt, sink);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Does evaluating this expression do anything besides computing its value?
+///
+/// Function calls count as side effects without looking into the callee.
+pub(crate) fn has_side_effect(expr: &Expr) -> bool {
+    match &expr.var {
+        ExprVariant::FunctionCall(_) => true,
+        ExprVariant::BinaryOp(b) => match b.op {
+            OpVar::_Asn | OpVar::_Csn => true,
+            _ => has_side_effect(&b.lhs.borrow()) || has_side_effect(&b.rhs.borrow()),
+        },
+        ExprVariant::UnaryOp(u) => match u.op {
+            OpVar::Ina | OpVar::Inb | OpVar::Dea | OpVar::Deb => true,
+            _ => has_side_effect(&u.val.borrow()),
+        },
+        ExprVariant::TypeConversion(t) => has_side_effect(&t.expr.borrow()),
+        ExprVariant::StructChild(s) => has_side_effect(&s.val.borrow()),
+        ExprVariant::ArrayChild(a) => {
+            has_side_effect(&a.val.borrow()) || has_side_effect(&a.idx.borrow())
+        }
+        ExprVariant::Ident(_) | ExprVariant::Literal(_) => false,
+    }
+}
@@ -8,3 +8,12 @@ pub mod parser;
 pub mod ast;
 
 pub mod err;
+
+/// Static call graph analysis
+pub mod callgraph;
+
+/// Warnings about suspicious but valid code
+pub mod lint;
+
+/// Human-readable AST dump
+pub mod dump;
@@ -4,6 +4,9 @@ use super::lexer::*;
 use crate::prelude::*;
 use std::iter::Iterator;
 
+/// Types injected into the root scope by `Parser::inject_std`
+const BUILTIN_TYPES: &[&str] = &["void", "int", "double", "char"];
+
 pub trait IntoParser<T>
 where
     T: Iterator<Item = Token>,
@@ -26,6 +29,8 @@ where
 {
     lexer: T,
     cur: Token,
+    /// Number of tokens consumed so far, used to detect loops that make no progress
+    bumps: usize,
 }
 
 impl<T> Parser<T>
@@ -39,6 +44,7 @@ where
             lexer,
             // type_var: TypeVar::new(),
             cur: Token::dummy(),
+            bumps: 0,
         };
         parser.bump();
         parser
@@ -47,11 +53,32 @@ where
     fn bump(&mut self) -> Token {
         let mut next = self.lexer.next().unwrap_or_else(|| Token::eof());
         std::mem::swap(&mut self.cur, &mut next);
+        self.bumps += 1;
 
         log::trace!("Bump token pointer. Current: {:#}", self.cur);
         next
     }
 
+    /// Report an internal error if no token was consumed since `last`.
+    ///
+    /// Loops whose body is not guaranteed to consume a token call this once
+    /// per iteration, so a token that is never consumed turns into a
+    /// diagnostic instead of a hang. Loops that start each iteration by
+    /// taking a separator don't need it.
+    fn ensure_progress(&self, last: usize, place: &str) -> ParseResult<usize> {
+        if self.bumps == last {
+            Err(parse_err(
+                ParseErrVariant::InternalErr(format!(
+                    "Parser made no progress in {} at token {}",
+                    place, self.cur.var
+                )),
+                self.cur.span,
+            ))
+        } else {
+            Ok(self.bumps)
+        }
+    }
+
     fn check(&self, accept: &TokenType) -> bool {
         variant_eq(&self.cur.var, accept)
     }
@@ -141,6 +168,21 @@ where
         self.p_program()
     }
 
+    /// Builtin types cannot be redeclared in any scope, so a type name always
+    /// means the same type. `print` and `scan` are keywords and never reach
+    /// this check.
+    fn check_not_builtin(ident: &Token) -> ParseResult<()> {
+        let name = ident.get_ident().unwrap();
+        if BUILTIN_TYPES.contains(&name) {
+            Err(parse_err(
+                ParseErrVariant::BuiltinRedeclaration(name.into()),
+                ident.span,
+            ))
+        } else {
+            Ok(())
+        }
+    }
+
     fn inject_std(scope: Ptr<Scope>) {
         log::info!("Injecting std types");
         let mut scope = scope.borrow_mut();
@@ -200,8 +242,10 @@ where
         let root_scope = Ptr::new(Scope::new());
         Self::inject_std(root_scope.cp());
         let mut stmts = Vec::new();
+        let mut last = self.bumps;
         while self.cur.var != TokenType::EndOfFile {
-            stmts.push(self.p_decl_stmt(root_scope.cp())?)
+            stmts.push(self.p_decl_stmt(root_scope.cp())?);
+            last = self.ensure_progress(last, "program")?;
         }
         log::info!("Finished parsing program");
         Ok(Program {
@@ -314,9 +358,11 @@ where
         let mut stmts = Vec::new();
 
         // For each statement, parse
+        let mut last = self.bumps;
         while !self.check(&TokenType::RCurlyBrace) {
             let stmt = self.p_stmt(scope.cp())?;
             stmts.push(stmt);
+            last = self.ensure_progress(last, "block")?;
         }
 
         let r_span = self.cur.span;
@@ -390,6 +436,7 @@ where
             let param_type = self.p_type_name(scope.cp())?;
             self.check_report(&TokenType::Identifier(String::new()))?;
             let ident = self.bump();
+            Self::check_not_builtin(&ident)?;
             let ident_str = ident.get_ident().unwrap();
             inner_scope.insert_def(
                 ident_str,
@@ -404,6 +451,7 @@ where
                 let param_type = self.p_type_name(scope.cp())?;
                 self.check_report(&TokenType::Identifier(String::new()))?;
                 let ident = self.bump();
+                Self::check_not_builtin(&ident)?;
                 let ident_str = ident.get_ident().unwrap();
                 inner_scope.insert_def(
                     ident_str,
@@ -480,6 +528,7 @@ where
             self.check_report(&TokenType::Identifier(String::new()))?;
             let mut span = self.cur.span;
             let ident = self.bump();
+            Self::check_not_builtin(&ident)?;
 
             if self.check(&TokenType::LParenthesis) {
                 // * This checks if the ident declared is a function. If true,
@@ -686,8 +735,10 @@ where
         scope: Ptr<Scope>,
     ) -> ParseResult<Ptr<Expr>> {
         let mut expr = None;
+        let mut last = self.bumps;
         while !self.check_one_of(close_delim) {
             expr = Some(self.p_binary_op(expr, 0, close_delim, scope.cp())?);
+            last = self.ensure_progress(last, "expression")?;
         }
         expr.ok_or_else(|| {
             parse_err_z(ParseErrVariant::InternalErr(
@@ -0,0 +1,829 @@
+use super::err::*;
+use crate::c0::ast::{self, *};
+use crate::c0::lint::has_side_effect;
+use crate::prelude::*;
+use indexmap::IndexMap;
+use std::cell::{Cell, RefCell};
+use std::fmt::Write;
+
+/// Name of the generated function that runs non-constant global initializers
+const START_FN: &str = "c0_start";
+
+/// Prefix of temporaries that fix the evaluation order of operands
+const TEMP_PREFIX: &str = "c0_t";
+
+/// Prefix of user identifiers that would clash with C or with generated names
+const RENAME_PREFIX: &str = "c0_";
+
+/// Keywords of C89, then what `stdio.h`, `stdlib.h`, `string.h` and `math.h`
+/// declare, separated by whitespace. gcc knows most of these functions as
+/// builtins even when their header is not included, so they can't be user
+/// identifiers either.
+const RESERVED: &str = "
+    auto break case char const continue default do double else enum extern float for goto if int
+    long register return short signed sizeof static struct switch typedef union unsigned void
+    volatile while
+    BUFSIZ EOF FILE FILENAME_MAX FOPEN_MAX L_tmpnam NULL SEEK_CUR SEEK_END SEEK_SET TMP_MAX
+    _IOFBF _IOLBF _IONBF clearerr fclose feof ferror fflush fgetc fgetpos fgets fopen fpos_t
+    fprintf fputc fputs fread freopen fscanf fseek fsetpos ftell fwrite getc getchar gets perror
+    printf putc putchar puts remove rename rewind scanf setbuf setvbuf size_t sprintf sscanf
+    stderr stdin stdout tmpfile tmpnam ungetc vfprintf vprintf vsprintf
+    EXIT_FAILURE EXIT_SUCCESS MB_CUR_MAX RAND_MAX abort abs atexit atof atoi atol bsearch calloc
+    div div_t exit free getenv labs ldiv ldiv_t malloc mblen mbstowcs mbtowc qsort rand realloc
+    srand strtod strtol strtoul system wchar_t wcstombs wctomb
+    memchr memcmp memcpy memmove memset strcat strchr strcmp strcoll strcpy strcspn strerror
+    strlen strncat strncmp strncpy strpbrk strrchr strspn strstr strtok strxfrm
+    HUGE_VAL acos asin atan atan2 ceil cos cosh exp fabs floor fmod frexp ldexp log log10 modf
+    pow sin sinh sqrt tan tanh
+";
+
+/// Code that has to run before the statement being emitted
+#[derive(Debug, Clone)]
+enum Temp {
+    /// Declaration of a temporary, like `int c0_t0 = f(1);`
+    Decl(String),
+    /// A statement, like `c0_t0 = g() != 0;`
+    Stmt(String),
+    /// Code that only runs if the condition holds
+    If(String, Vec<Temp>),
+}
+
+/// Value categories that decide `printf`/`scanf` conversions
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum CTy {
+    Int,
+    Double,
+    Char,
+    Str,
+    Void,
+}
+
+/// Transpiles a C0 program back into portable C89 source.
+///
+/// Global initializers that are plain literals stay static initializers. All
+/// the others are moved into `c0_start()`, which is called at the beginning of
+/// `main`, mirroring the start code of the minivm backend.
+///
+/// C0 evaluates operands and arguments from left to right, while C leaves
+/// the order unspecified. Whenever the order could be observed, operands are
+/// first stored into temporaries in a block around the statement.
+///
+/// `main` always returns `int`, so a `void main` exits with 0 as it does in
+/// the VM. User identifiers that are reserved in C, or that start with
+/// `c0_`, are prefixed with `c0_`.
+pub struct Codegen<'a> {
+    prog: &'a ast::Program,
+    out: String,
+    indent: usize,
+    /// Code to run before the statement being emitted
+    temps: RefCell<Vec<Temp>>,
+    /// Number of temporaries in the current function
+    temp_cnt: Cell<usize>,
+    /// Whether the function being emitted is a `void main`
+    void_main: bool,
+}
+
+impl<'a> Codegen<'a> {
+    pub fn new(prog: &'a ast::Program) -> Codegen<'a> {
+        Codegen {
+            prog,
+            out: String::new(),
+            indent: 0,
+            temps: RefCell::new(Vec::new()),
+            temp_cnt: Cell::new(0),
+            void_main: false,
+        }
+    }
+
+    pub fn compile(mut self) -> CompileResult<String> {
+        let root = self.prog.blk.scope.cp();
+
+        // * Split global initializers into static ones and ones run at startup
+        let mut static_init: IndexMap<String, Ptr<Expr>> = IndexMap::new();
+        let mut start_init = Vec::new();
+        for stmt in &self.prog.blk.stmts {
+            if let StmtVariant::ManyExpr(exprs) = &stmt.var {
+                for e in exprs {
+                    match global_init(&e.borrow()) {
+                        Some((name, rhs)) if is_literal(&rhs.borrow()) => {
+                            static_init.insert(name, rhs);
+                        }
+                        _ => start_init.push(e.cp()),
+                    }
+                }
+            }
+        }
+
+        self.line("#include <stdio.h>")?;
+
+        let mut fns = Vec::new();
+        {
+            let defs = root.borrow();
+            if defs.defs.values().any(|d| match &*d.borrow() {
+                SymbolDef::Var { typ, .. } => !typ.borrow().is_fn(),
+                _ => false,
+            }) {
+                self.line("")?;
+            }
+            for (name, def) in defs.defs.iter() {
+                if let SymbolDef::Var { typ, is_const, .. } = &*def.borrow() {
+                    let typ = typ.borrow();
+                    match &*typ {
+                        TypeDef::Function(f) => fns.push((name.clone(), f.clone())),
+                        _ => match static_init.get(name) {
+                            Some(init) => {
+                                let qual = if *is_const { "const " } else { "" };
+                                let init = self.expr(init.cp(), root.cp())?;
+                                let decl = format!(
+                                    "{}{} {} = {};",
+                                    qual,
+                                    c_type(&typ)?,
+                                    c_name(name),
+                                    init
+                                );
+                                self.line(&decl)?;
+                            }
+                            None => {
+                                let decl = format!("{} {};", c_type(&typ)?, c_name(name));
+                                self.line(&decl)?;
+                            }
+                        },
+                    }
+                }
+            }
+        }
+        self.line("")?;
+
+        // * Prototypes, so functions can be defined in any order
+        for (name, f) in &fns {
+            let params = if f.params.is_empty() {
+                "void".to_owned()
+            } else {
+                f.params
+                    .iter()
+                    .map(|p| c_type(&p.borrow()))
+                    .collect::<CompileResult<Vec<_>>>()?
+                    .join(", ")
+            };
+            let proto = format!("{} {}({});", ret_type(name, f)?, c_name(name), params);
+            self.line(&proto)?;
+        }
+        if !start_init.is_empty() {
+            self.line(&format!("static void {}(void);", START_FN))?;
+        }
+        self.line("")?;
+
+        if !start_init.is_empty() {
+            self.open(&format!("static void {}(void)", START_FN))?;
+            for e in &start_init {
+                let e = self.top_expr(e.cp(), root.cp())?;
+                self.sequenced(&format!("{};", e))?;
+            }
+            self.close()?;
+            self.line("")?;
+        }
+
+        for (name, f) in &fns {
+            let prologue = if name == "main" && !start_init.is_empty() {
+                Some(format!("{}();", START_FN))
+            } else {
+                None
+            };
+            self.emit_fn(name, f, prologue)?;
+            self.line("")?;
+        }
+
+        Ok(self.out)
+    }
+
+    fn emit_fn(
+        &mut self,
+        name: &str,
+        f: &ast::FunctionType,
+        prologue: Option<String>,
+    ) -> CompileResult<()> {
+        let body = match &f.body {
+            Some(b) => b,
+            None => return Ok(()),
+        };
+
+        // * Parameters are the first definitions in the function's scope
+        let params = {
+            let scope = body.scope.borrow();
+            let params = f
+                .params
+                .iter()
+                .zip(scope.defs.keys())
+                .map(|(typ, name)| Ok(format!("{} {}", c_type(&typ.borrow())?, c_name(name))))
+                .collect::<CompileResult<Vec<_>>>()?;
+            if params.is_empty() {
+                "void".to_owned()
+            } else {
+                params.join(", ")
+            }
+        };
+
+        let head = format!("{} {}({})", ret_type(name, f)?, c_name(name), params);
+        self.temp_cnt.set(0);
+        self.void_main = name == "main" && c_ty(&f.return_type.borrow())? == CTy::Void;
+        self.open(&head)?;
+        self.emit_decls(body.scope.cp(), f.params.len())?;
+        if let Some(p) = prologue {
+            self.line(&p)?;
+        }
+        for stmt in &body.stmts {
+            self.emit_stmt(stmt, body.scope.cp())?;
+        }
+        if self.void_main {
+            self.line("return 0;")?;
+        }
+        self.close()
+    }
+
+    /// Declare every variable of `scope` up front, as C89 requires.
+    fn emit_decls(&mut self, scope: Ptr<Scope>, skip: usize) -> CompileResult<()> {
+        let scope = scope.borrow();
+        for (name, def) in scope.defs.iter().skip(skip) {
+            if let SymbolDef::Var { typ, .. } = &*def.borrow() {
+                let typ = typ.borrow();
+                if !typ.is_fn() {
+                    let decl = format!("{} {};", c_type(&typ)?, c_name(name));
+                    self.line(&decl)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Emit the contents of a braced body. Blocks are flattened into it.
+    fn emit_body(&mut self, stmt: &Stmt, scope: Ptr<Scope>) -> CompileResult<()> {
+        match &stmt.var {
+            StmtVariant::Block(b) => {
+                self.emit_decls(b.scope.cp(), 0)?;
+                for stmt in &b.stmts {
+                    self.emit_stmt(stmt, b.scope.cp())?;
+                }
+                Ok(())
+            }
+            _ => self.emit_stmt(stmt, scope),
+        }
+    }
+
+    fn emit_stmt(&mut self, stmt: &Stmt, scope: Ptr<Scope>) -> CompileResult<()> {
+        match &stmt.var {
+            StmtVariant::Block(_) => {
+                self.open("")?;
+                self.emit_body(stmt, scope)?;
+                self.close()
+            }
+            StmtVariant::If(i) => {
+                let cond = self.top_expr(i.cond.cp(), scope.cp())?;
+                let blocks = self.emit_temps()?;
+                self.open(&format!("if ({})", cond))?;
+                self.emit_body(&i.if_block.borrow(), scope.cp())?;
+                if let Some(else_block) = &i.else_block {
+                    self.indent -= 1;
+                    self.line("} else {")?;
+                    self.indent += 1;
+                    self.emit_body(&else_block.borrow(), scope.cp())?;
+                }
+                self.close()?;
+                self.close_n(blocks)
+            }
+            StmtVariant::While(w) => {
+                let cond = self.top_expr(w.cond.cp(), scope.cp())?;
+                if self.temps.borrow().is_empty() {
+                    self.open(&format!("while ({})", cond))?;
+                    self.emit_body(&w.block.borrow(), scope)?;
+                    return self.close();
+                }
+                // * The temporaries must be computed again before every check
+                self.open("while (1)")?;
+                let blocks = self.emit_pending()?;
+                self.line(&format!("if (!({})) break;", cond))?;
+                self.open("")?;
+                self.emit_body(&w.block.borrow(), scope)?;
+                self.close()?;
+                self.close_n(blocks)?;
+                self.close()
+            }
+            StmtVariant::Expr(e) => {
+                let e = self.top_expr(e.cp(), scope)?;
+                self.sequenced(&format!("{};", e))
+            }
+            StmtVariant::ManyExpr(es) => {
+                for e in es {
+                    let e = self.top_expr(e.cp(), scope.cp())?;
+                    self.sequenced(&format!("{};", e))?;
+                }
+                Ok(())
+            }
+            StmtVariant::Print(es) => {
+                let mut specs = Vec::new();
+                for e in es {
+                    specs.push(match self.expr_ty(e.cp(), scope.cp())? {
+                        CTy::Int => "%d",
+                        CTy::Double => "%f",
+                        CTy::Char => "%c",
+                        CTy::Str => "%s",
+                        CTy::Void => {
+                            return Err(CompileError::UnsupportedExpr(format!(
+                                "print of void expression {}",
+                                &*e.borrow()
+                            )))
+                        }
+                    });
+                }
+                let args = self.operands(es, scope, false)?;
+                self.sequenced(&format!(
+                    "printf(\"{}\\n\", {});",
+                    specs.join(" "),
+                    args.join(", ")
+                ))
+            }
+            StmtVariant::Scan(ident) => {
+                let spec = match self.ident_ty(&ident.name, scope)? {
+                    CTy::Int => "%d",
+                    CTy::Double => "%lf",
+                    CTy::Char => "%c",
+                    _ => return Err(CompileError::UnsupportedType(ident.name.clone())),
+                };
+                self.line(&format!("scanf(\"{}\", &{});", spec, c_name(&ident.name)))
+            }
+            StmtVariant::Return(None) if self.void_main => self.line("return 0;"),
+            StmtVariant::Return(None) => self.line("return;"),
+            StmtVariant::Return(Some(e)) => {
+                let e = self.top_expr(e.cp(), scope)?;
+                self.sequenced(&format!("return {};", e))
+            }
+            StmtVariant::Break => self.line("break;"),
+            StmtVariant::Empty => Ok(()),
+        }
+    }
+
+    /// Format an expression without parentheses around its outermost operator
+    fn top_expr(&self, e: Ptr<Expr>, scope: Ptr<Scope>) -> CompileResult<String> {
+        self.expr_inner(e, scope, false)
+    }
+
+    fn expr(&self, e: Ptr<Expr>, scope: Ptr<Scope>) -> CompileResult<String> {
+        self.expr_inner(e, scope, true)
+    }
+
+    fn expr_inner(&self, e: Ptr<Expr>, scope: Ptr<Scope>, wrap: bool) -> CompileResult<String> {
+        let e = e.borrow();
+        let s = match &e.var {
+            ExprVariant::Ident(i) => return Ok(c_name(&i.name)),
+            ExprVariant::Literal(lit) => return literal(lit),
+            ExprVariant::TypeConversion(t) => format!(
+                "({}) {}",
+                c_type(&t.to.borrow())?,
+                self.expr(t.expr.cp(), scope)?
+            ),
+            ExprVariant::UnaryOp(u) => {
+                let val = self.expr(u.val.cp(), scope)?;
+                match u.op {
+                    OpVar::Neg => format!("-{}", val),
+                    OpVar::Pos => format!("+{}", val),
+                    OpVar::Inv => format!("!{}", val),
+                    OpVar::Bin => format!("~{}", val),
+                    OpVar::Ref => format!("&{}", val),
+                    OpVar::Der => format!("*{}", val),
+                    OpVar::Ina => format!("{}++", val),
+                    OpVar::Inb => format!("++{}", val),
+                    OpVar::Dea => format!("{}--", val),
+                    OpVar::Deb => format!("--{}", val),
+                    op => return Err(CompileError::UnsupportedExpr(format!("{}", op))),
+                }
+            }
+            ExprVariant::BinaryOp(b) => {
+                let op = match b.op {
+                    OpVar::Add => "+",
+                    OpVar::Sub => "-",
+                    OpVar::Mul => "*",
+                    OpVar::Div => "/",
+                    OpVar::And => "&&",
+                    OpVar::Or => "||",
+                    OpVar::Xor => "^",
+                    OpVar::Ban => "&",
+                    OpVar::Bor => "|",
+                    OpVar::Gt => ">",
+                    OpVar::Lt => "<",
+                    OpVar::Eq => "==",
+                    OpVar::Gte => ">=",
+                    OpVar::Lte => "<=",
+                    OpVar::Neq => "!=",
+                    OpVar::_Com => ",",
+                    OpVar::_Asn | OpVar::_Csn => "=",
+                    op => return Err(CompileError::UnsupportedExpr(format!("{}", op))),
+                };
+                let (lhs, rhs) = match b.op {
+                    OpVar::_Asn | OpVar::_Csn => (
+                        self.expr(b.lhs.cp(), scope.cp())?,
+                        self.top_expr(b.rhs.cp(), scope)?,
+                    ),
+                    // * C already evaluates these left to right. Temporaries of
+                    // * the rhs are computed before the whole statement, so
+                    // * they must be kept after the lhs, and must not run at
+                    // * all if `&&` or `||` skips the rhs.
+                    OpVar::And | OpVar::Or | OpVar::_Com => {
+                        let lhs = self.expr(b.lhs.cp(), scope.cp())?;
+                        let outer = self.temps.replace(Vec::new());
+                        let rhs = self.expr(b.rhs.cp(), scope);
+                        let inner = self.temps.replace(outer);
+                        let rhs = rhs?;
+                        if inner.is_empty() {
+                            (lhs, rhs)
+                        } else if b.op == OpVar::_Com {
+                            let mut temps = self.temps.borrow_mut();
+                            if has_side_effect(&b.lhs.borrow()) {
+                                temps.push(Temp::Stmt(format!("{};", lhs)));
+                            }
+                            temps.extend(inner);
+                            return Ok(rhs);
+                        } else {
+                            return Ok(self.guard(b.op, lhs, rhs, inner));
+                        }
+                    }
+                    _ => {
+                        let mut ops = self.operands(&[b.lhs.cp(), b.rhs.cp()], scope, true)?;
+                        let rhs = ops.pop().unwrap();
+                        (ops.pop().unwrap(), rhs)
+                    }
+                };
+                format!("{} {} {}", lhs, op, rhs)
+            }
+            ExprVariant::FunctionCall(f) => {
+                let params = self.operands(&f.params, scope, false)?;
+                return Ok(format!("{}({})", c_name(&f.func), params.join(", ")));
+            }
+            _ => return Err(CompileError::UnsupportedExpr(format!("{}", &*e))),
+        };
+        if wrap {
+            Ok(format!("({})", s))
+        } else {
+            Ok(s)
+        }
+    }
+
+    /// Format operands so they are evaluated left to right. If any of them
+    /// has a side effect, the operands before the last such one are stored
+    /// into temporaries first, and so is that one if anything after it might
+    /// see the effect. Literals, and locals that no later operand assigns,
+    /// read the same value either way and are left in place.
+    fn operands(
+        &self,
+        es: &[Ptr<Expr>],
+        scope: Ptr<Scope>,
+        wrap: bool,
+    ) -> CompileResult<Vec<String>> {
+        let last = match es.len() {
+            0 | 1 => None,
+            _ => es.iter().rposition(|e| has_side_effect(&e.borrow())),
+        };
+        let stable = |i: usize| {
+            let e = es[i].borrow();
+            is_literal(&e)
+                || (self.is_local(&e, scope.cp())
+                    && !es[i + 1..].iter().any(|e| has_write(&e.borrow())))
+        };
+        es.iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let s = self.expr_inner(e.cp(), scope.cp(), wrap)?;
+                let hoist = match last {
+                    Some(last) if i < last => !stable(i),
+                    Some(last) if i == last => (i + 1..es.len()).any(|j| !stable(j)),
+                    _ => false,
+                };
+                if hoist {
+                    self.temp(e.cp(), scope.cp(), s)
+                } else {
+                    Ok(s)
+                }
+            })
+            .collect()
+    }
+
+    /// Is this a local variable? Function calls cannot change those.
+    fn is_local(&self, e: &Expr, scope: Ptr<Scope>) -> bool {
+        let root = self.prog.blk.scope.borrow().id;
+        match &e.var {
+            ExprVariant::Ident(i) => match scope.borrow().find_def_depth(&i.name) {
+                Some((_, id)) => id != root,
+                None => false,
+            },
+            _ => false,
+        }
+    }
+
+    /// Store `code`, the formatted value of `e`, into a new temporary
+    fn temp(&self, e: Ptr<Expr>, scope: Ptr<Scope>, code: String) -> CompileResult<String> {
+        let typ = match self.expr_ty(e.cp(), scope)? {
+            CTy::Int => "int",
+            CTy::Double => "double",
+            CTy::Char => "char",
+            _ => return Err(CompileError::UnsupportedExpr(format!("{}", &*e.borrow()))),
+        };
+        let name = self.temp_name();
+        self.temps
+            .borrow_mut()
+            .push(Temp::Decl(format!("{} {} = {};", typ, name, code)));
+        Ok(name)
+    }
+
+    fn temp_name(&self) -> String {
+        let name = format!("{}{}", TEMP_PREFIX, self.temp_cnt.get());
+        self.temp_cnt.set(self.temp_cnt.get() + 1);
+        name
+    }
+
+    /// Compute `lhs && rhs` or `lhs || rhs` into a new temporary, where `rhs`
+    /// needs the temporaries `inner`. They only run if `rhs` is evaluated.
+    fn guard(&self, op: OpVar, lhs: String, rhs: String, inner: Vec<Temp>) -> String {
+        let name = self.temp_name();
+        let cond = match op {
+            OpVar::And => name.clone(),
+            _ => format!("!{}", name),
+        };
+        let mut body = inner;
+        body.push(Temp::Stmt(format!("{} = {} != 0;", name, rhs)));
+
+        let mut temps = self.temps.borrow_mut();
+        temps.push(Temp::Decl(format!("int {} = {} != 0;", name, lhs)));
+        temps.push(Temp::If(cond, body));
+        name
+    }
+
+    /// Emit `temps` in order. C89 only allows declarations at the start of a
+    /// block, so a block is opened for declarations that follow a statement.
+    /// Returns the number of blocks opened.
+    fn emit_temp_list(&mut self, temps: Vec<Temp>) -> CompileResult<usize> {
+        let mut blocks = 0;
+        let mut after_stmt = false;
+        for t in temps {
+            match t {
+                Temp::Decl(decl) => {
+                    if after_stmt {
+                        self.open("")?;
+                        blocks += 1;
+                        after_stmt = false;
+                    }
+                    self.line(&decl)?;
+                }
+                Temp::Stmt(stmt) => {
+                    self.line(&stmt)?;
+                    after_stmt = true;
+                }
+                Temp::If(cond, body) => {
+                    self.open(&format!("if ({})", cond))?;
+                    let inner = self.emit_temp_list(body)?;
+                    self.close_n(inner)?;
+                    self.close()?;
+                    after_stmt = true;
+                }
+            }
+        }
+        Ok(blocks)
+    }
+
+    /// Emit the pending temporaries in the current block. Returns the number
+    /// of blocks opened.
+    fn emit_pending(&mut self) -> CompileResult<usize> {
+        let temps = self.temps.take();
+        self.emit_temp_list(temps)
+    }
+
+    /// Open a block with the pending temporaries, if there are any. Returns
+    /// the number of blocks opened.
+    fn emit_temps(&mut self) -> CompileResult<usize> {
+        if self.temps.borrow().is_empty() {
+            return Ok(0);
+        }
+        self.open("")?;
+        Ok(self.emit_pending()? + 1)
+    }
+
+    /// Emit a simple statement after the temporaries it uses
+    fn sequenced(&mut self, stmt: &str) -> CompileResult<()> {
+        let blocks = self.emit_temps()?;
+        self.line(stmt)?;
+        self.close_n(blocks)
+    }
+
+    /// Infer the value category of an expression. Follows the minivm backend:
+    /// mixed arithmetic is `double` if either side is, otherwise the left
+    /// hand side's type.
+    fn expr_ty(&self, e: Ptr<Expr>, scope: Ptr<Scope>) -> CompileResult<CTy> {
+        let e = e.borrow();
+        match &e.var {
+            ExprVariant::Ident(i) => self.ident_ty(&i.name, scope),
+            ExprVariant::Literal(lit) => Ok(match lit {
+                Literal::Char { .. } => CTy::Char,
+                Literal::Float { .. } => CTy::Double,
+                Literal::String { .. } => CTy::Str,
+                _ => CTy::Int,
+            }),
+            ExprVariant::TypeConversion(t) => c_ty(&t.to.borrow()),
+            ExprVariant::UnaryOp(u) => self.expr_ty(u.val.cp(), scope),
+            ExprVariant::BinaryOp(b) => match b.op {
+                OpVar::Gt | OpVar::Lt | OpVar::Eq | OpVar::Gte | OpVar::Lte | OpVar::Neq => {
+                    Ok(CTy::Int)
+                }
+                OpVar::And | OpVar::Or => Ok(CTy::Int),
+                OpVar::_Com => self.expr_ty(b.rhs.cp(), scope),
+                OpVar::_Asn | OpVar::_Csn => Ok(CTy::Void),
+                _ => {
+                    let lhs = self.expr_ty(b.lhs.cp(), scope.cp())?;
+                    let rhs = self.expr_ty(b.rhs.cp(), scope)?;
+                    if rhs == CTy::Double {
+                        Ok(CTy::Double)
+                    } else {
+                        Ok(lhs)
+                    }
+                }
+            },
+            ExprVariant::FunctionCall(f) => self.ident_ty(&f.func, scope),
+            _ => Err(CompileError::UnsupportedExpr(format!("{}", &*e))),
+        }
+    }
+
+    /// Type of a variable, or the return type of a function
+    fn ident_ty(&self, name: &str, scope: Ptr<Scope>) -> CompileResult<CTy> {
+        let def = scope
+            .borrow()
+            .find_def(name)
+            .ok_or_else(|| CompileError::UnsupportedExpr(name.into()))?;
+        let def = def.borrow();
+        match &*def {
+            SymbolDef::Var { typ, .. } => c_ty(&typ.borrow()),
+            SymbolDef::Typ { .. } => Err(CompileError::UnsupportedExpr(name.into())),
+        }
+    }
+
+    fn line(&mut self, s: &str) -> CompileResult<()> {
+        if s.is_empty() {
+            writeln!(self.out)?;
+        } else {
+            writeln!(self.out, "{:indent$}{}", "", s, indent = self.indent * 4)?;
+        }
+        Ok(())
+    }
+
+    fn open(&mut self, head: &str) -> CompileResult<()> {
+        if head.is_empty() {
+            self.line("{")?;
+        } else {
+            self.line(&format!("{} {{", head))?;
+        }
+        self.indent += 1;
+        Ok(())
+    }
+
+    fn close(&mut self) -> CompileResult<()> {
+        self.indent -= 1;
+        self.line("}")
+    }
+
+    fn close_n(&mut self, n: usize) -> CompileResult<()> {
+        for _ in 0..n {
+            self.close()?;
+        }
+        Ok(())
+    }
+}
+
+/// Name of a user identifier in the C source
+fn c_name(name: &str) -> String {
+    if RESERVED.split_whitespace().any(|r| r == name) || name.starts_with(RENAME_PREFIX) {
+        format!("{}{}", RENAME_PREFIX, name)
+    } else {
+        name.to_owned()
+    }
+}
+
+/// C return type of function `name`
+fn ret_type(name: &str, f: &ast::FunctionType) -> CompileResult<String> {
+    let ret = f.return_type.borrow();
+    if name == "main" && c_ty(&ret)? == CTy::Void {
+        Ok("int".into())
+    } else {
+        c_type(&ret)
+    }
+}
+
+/// Split a global `name = value` initializer into its parts
+fn global_init(e: &Expr) -> Option<(String, Ptr<Expr>)> {
+    match &e.var {
+        ExprVariant::BinaryOp(b) if b.op == OpVar::_Asn || b.op == OpVar::_Csn => {
+            match &b.lhs.borrow().var {
+                ExprVariant::Ident(i) => Some((i.name.clone(), b.rhs.cp())),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Does this expression assign to a variable?
+fn has_write(e: &Expr) -> bool {
+    match &e.var {
+        ExprVariant::BinaryOp(b) => {
+            b.op == OpVar::_Asn
+                || b.op == OpVar::_Csn
+                || has_write(&b.lhs.borrow())
+                || has_write(&b.rhs.borrow())
+        }
+        ExprVariant::UnaryOp(u) => match u.op {
+            OpVar::Ina | OpVar::Inb | OpVar::Dea | OpVar::Deb => true,
+            _ => has_write(&u.val.borrow()),
+        },
+        ExprVariant::FunctionCall(f) => f.params.iter().any(|p| has_write(&p.borrow())),
+        ExprVariant::TypeConversion(t) => has_write(&t.expr.borrow()),
+        ExprVariant::StructChild(s) => has_write(&s.val.borrow()),
+        ExprVariant::ArrayChild(a) => has_write(&a.val.borrow()) || has_write(&a.idx.borrow()),
+        ExprVariant::Ident(_) | ExprVariant::Literal(_) => false,
+    }
+}
+
+/// Is this expression a (possibly signed) literal, usable as a static initializer?
+fn is_literal(e: &Expr) -> bool {
+    match &e.var {
+        ExprVariant::Literal(Literal::Struct { .. }) => false,
+        ExprVariant::Literal(..) => true,
+        ExprVariant::UnaryOp(u) if u.op == OpVar::Neg || u.op == OpVar::Pos => {
+            is_literal(&u.val.borrow())
+        }
+        _ => false,
+    }
+}
+
+fn c_type(ty: &TypeDef) -> CompileResult<String> {
+    match ty {
+        TypeDef::NamedType(n) => Ok(n.clone()),
+        TypeDef::Unit => Ok("void".into()),
+        TypeDef::Primitive(p) => Ok(match p.var {
+            PrimitiveTypeVar::Float => "double".into(),
+            PrimitiveTypeVar::UnsignedInt if p.occupy_bytes == 1 => "char".into(),
+            _ => "int".into(),
+        }),
+        TypeDef::Ref(r) => Ok(format!("{} *", c_type(&r.target.borrow())?)),
+        TypeDef::Array(a) => Ok(format!("{} *", c_type(&a.target.borrow())?)),
+        _ => Err(CompileError::UnsupportedType(format!("{:?}", ty))),
+    }
+}
+
+fn c_ty(ty: &TypeDef) -> CompileResult<CTy> {
+    match ty {
+        TypeDef::NamedType(n) => match &n[..] {
+            "int" => Ok(CTy::Int),
+            "double" => Ok(CTy::Double),
+            "char" => Ok(CTy::Char),
+            "void" => Ok(CTy::Void),
+            _ => Err(CompileError::UnsupportedType(n.clone())),
+        },
+        TypeDef::Unit => Ok(CTy::Void),
+        TypeDef::Primitive(p) => Ok(match p.var {
+            PrimitiveTypeVar::Float => CTy::Double,
+            PrimitiveTypeVar::UnsignedInt if p.occupy_bytes == 1 => CTy::Char,
+            _ => CTy::Int,
+        }),
+        TypeDef::Ref(..) => Ok(CTy::Str),
+        TypeDef::Function(f) => c_ty(&f.return_type.borrow()),
+        _ => Err(CompileError::UnsupportedType(format!("{:?}", ty))),
+    }
+}
+
+fn literal(lit: &Literal) -> CompileResult<String> {
+    match lit {
+        Literal::Integer { val } => Ok(format!("{}", val)),
+        Literal::Float { val } => Ok(format!("{:?}", val.to_f64())),
+        Literal::Boolean { val } => Ok(if *val { "1" } else { "0" }.into()),
+        Literal::Char { val } => Ok(match val {
+            '\'' => "'\\''".into(),
+            '\\' => "'\\\\'".into(),
+            '\n' => "'\\n'".into(),
+            '\r' => "'\\r'".into(),
+            '\t' => "'\\t'".into(),
+            ' '..='~' => format!("'{}'", val),
+            c => format!("{}", *c as u32),
+        }),
+        Literal::String { val } => {
+            let mut s = String::from("\"");
+            for b in val.bytes() {
+                match b {
+                    b'"' => s.push_str("\\\""),
+                    b'\\' => s.push_str("\\\\"),
+                    b'\n' => s.push_str("\\n"),
+                    b'\r' => s.push_str("\\r"),
+                    b'\t' => s.push_str("\\t"),
+                    b' '..=b'~' => s.push(b as char),
+                    b => write!(s, "\\{:03o}", b)?,
+                }
+            }
+            s.push('"');
+            Ok(s)
+        }
+        Literal::Struct { .. } => Err(CompileError::UnsupportedExpr(format!("{}", lit))),
+    }
+}
@@ -0,0 +1,23 @@
+use failure::*;
+use std::fmt;
+
+#[derive(Fail, Debug)]
+pub enum CompileError {
+    UnsupportedType(String),
+    UnsupportedExpr(String),
+    FmtError(fmt::Error),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl From<fmt::Error> for CompileError {
+    fn from(err: fmt::Error) -> Self {
+        CompileError::FmtError(err)
+    }
+}
+
+pub type CompileResult<T> = Result<T, CompileError>;
@@ -0,0 +1,5 @@
+pub mod codegen;
+pub mod err;
+
+pub use codegen::*;
+pub use err::*;
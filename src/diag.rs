@@ -0,0 +1,107 @@
+use crate::prelude::*;
+use std::fmt;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "Warning"),
+            Severity::Error => write!(f, "Error"),
+        }
+    }
+}
+
+/// A message about a position of the source file
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub span: Span,
+    pub msg: String,
+}
+
+impl fmt::Display for Diagnostic {
+    /// Positions are 1-based `line:col`, numbered like the source listing of
+    /// `pretty_print_error`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.span.start.ln + 1,
+            self.span.start.pos + 1,
+            self.severity,
+            self.msg
+        )
+    }
+}
+
+/// A collector of diagnostics that can be shared between threads.
+///
+/// Diagnostics may be reported in any order. They are handed out sorted by
+/// source position, so output does not depend on which thread came first.
+#[derive(Debug, Default)]
+pub struct DiagSink {
+    diags: Mutex<Vec<Diagnostic>>,
+}
+
+impl DiagSink {
+    pub fn new() -> DiagSink {
+        DiagSink::default()
+    }
+
+    pub fn push(&self, diag: Diagnostic) {
+        self.diags.lock().unwrap().push(diag);
+    }
+
+    pub fn warn(&self, span: Span, msg: impl Into<String>) {
+        self.push(Diagnostic {
+            severity: Severity::Warning,
+            span,
+            msg: msg.into(),
+        })
+    }
+
+    pub fn error(&self, span: Span, msg: impl Into<String>) {
+        self.push(Diagnostic {
+            severity: Severity::Error,
+            span,
+            msg: msg.into(),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.diags.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diags
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+    }
+
+    /// All diagnostics reported so far, ordered by source position. Ties
+    /// keep the order they were reported in.
+    pub fn sorted(&self) -> Vec<Diagnostic> {
+        let mut diags = self.diags.lock().unwrap().clone();
+        diags.sort_by_key(|d| (d.span.start, d.span.end));
+        diags
+    }
+
+    /// Take all diagnostics out of the sink, ordered like `sorted`.
+    pub fn drain(&self) -> Vec<Diagnostic> {
+        let mut diags = std::mem::take(&mut *self.diags.lock().unwrap());
+        diags.sort_by_key(|d| (d.span.start, d.span.end));
+        diags
+    }
+}
@@ -0,0 +1,13 @@
+/// Every program in `examples/`, by name, in the order `--example` lists them
+pub static EXAMPLES: &[(&str, &str)] = &[
+    ("fib", include_str!("../examples/fib.c")),
+    ("primes", include_str!("../examples/primes.c")),
+    ("sort", include_str!("../examples/sort.c")),
+    ("matrix", include_str!("../examples/matrix.c")),
+    ("reverse", include_str!("../examples/reverse.c")),
+];
+
+/// Source of the example called `name`
+pub fn find(name: &str) -> Option<&'static str> {
+    EXAMPLES.iter().find(|e| e.0 == name).map(|e| e.1)
+}
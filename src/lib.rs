@@ -7,6 +7,12 @@ pub mod c0;
 
 pub mod minivm;
 
+/// Transpiles C0 back into portable C89 source.
+pub mod c89;
+
+/// Common interface and registry of all code generation backends
+pub mod backend;
+
 /// Kurumi is a simple virtual machine for this project.
 // #[cfg(kurumi)]
 // pub mod kurumi;
@@ -18,8 +24,20 @@ pub mod cranelift;
 /// Essencial stuff
 pub mod prelude;
 
+/// Collecting warnings and errors for reporting
+pub mod diag;
+
+/// Example C0 programs bundled into the compiler
+pub mod examples;
+
+/// Version and feature set of this build
+mod build_info;
+pub use build_info::{build_info, BuildInfo};
+
 /// Stuff for binary program
 pub(crate) mod opt;
+// * Lets `opt` name this crate the same way in the library and the binary
+extern crate self as chigusa;
 
 #[cfg(test)]
 mod tests;
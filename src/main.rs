@@ -2,7 +2,7 @@ mod err_disp;
 mod opt;
 use chigusa::c0::lexer;
 use failure::Fail;
-use opt::{EmitOption, ParserConfig};
+use opt::{BuildOpts, Command, DebugOpts, EmitOption, ParserConfig};
 use std::fs::*;
 use std::io::{Read, Write};
 use std::path::PathBuf;
@@ -10,18 +10,37 @@ use structopt;
 use structopt::StructOpt;
 
 fn main() {
-    let mut opt: ParserConfig = ParserConfig::from_args();
-    cute_log::init_with_max_level(opt.verbosity).unwrap();
+    let opt: ParserConfig = ParserConfig::from_args();
 
-    if opt.output_assembly {
-        opt.emit = EmitOption::S0;
-    }
-    if opt.output_binary {
-        opt.emit = EmitOption::O0;
+    if opt.version {
+        let info = chigusa::build_info();
+        if opt.verbose {
+            print!("{}", info);
+        } else {
+            println!("chigusa {}", info.version);
+        }
+        return;
     }
 
+    let mut cmd = opt.into_command();
+    cute_log::init_with_max_level(cmd.input().verbosity).unwrap();
+
+    let backend = match &mut cmd {
+        Command::Build(opt) => Some(select_backend(opt)),
+        _ => None,
+    };
+
     let mut input = String::new();
-    if let Some(f) = &opt.input_file {
+    if let Some(name) = &cmd.input().example {
+        match chigusa::examples::find(name) {
+            Some(src) => input.push_str(src),
+            None => {
+                let names: Vec<_> = chigusa::examples::EXAMPLES.iter().map(|e| e.0).collect();
+                log::error!("Unknown example. Allowed are: {}", names.join(", "));
+                std::process::exit(1);
+            }
+        }
+    } else if let Some(f) = &cmd.input().input_file {
         std::fs::File::open(f)
             .expect("File does not exist!")
             .read_to_string(&mut input)
@@ -34,10 +53,12 @@ fn main() {
 
     let token = lexer::Lexer::new(Box::new(input.chars())).into_iter();
 
-    if opt.emit == EmitOption::Token {
-        let tokens: Vec<_> = token.collect();
-        write_output(&opt, tokens);
-        return;
+    if let Command::Build(opt) = &cmd {
+        if opt.emit == EmitOption::Token {
+            let tokens: Vec<_> = token.collect();
+            write_output(opt, tokens);
+            return;
+        }
     }
 
     let tree = chigusa::c0::parser::Parser::new(token).parse();
@@ -53,38 +74,199 @@ fn main() {
         }
     };
 
+    let diags = chigusa::diag::DiagSink::new();
+    chigusa::c0::lint::lint(&tree, &diags);
+    let has_errors = diags.has_errors();
+    // * Keep stdout clean for the emitted result or the program's output
+    let to_stderr = match &cmd {
+        Command::Build(opt) => opt.stdout,
+        Command::Run(_) | Command::Debug(_) => true,
+        Command::Check(_) => false,
+    };
+    print_diagnostics(to_stderr, &input, &diags);
+
+    match cmd {
+        Command::Check(_) => {
+            if let Err(e) = chigusa::backend::check(&tree) {
+                report_compile_error(&input, &e.msg, e.span);
+            }
+            std::process::exit(has_errors as i32)
+        }
+        Command::Run(_) => run(&input, &tree),
+        Command::Debug(opt) => debug(&opt, &input, &tree),
+        Command::Build(opt) => {
+            let backend = backend.expect("Build always selects a backend");
+            build(&opt, &input, &tree, backend)
+        }
+    }
+}
+
+/// Apply the `-s` and `-c` shorthands and look up the backend to emit with.
+/// `None` for emit options that are not backends.
+fn select_backend(opt: &mut BuildOpts) -> Option<Box<dyn chigusa::backend::Backend>> {
+    if opt.output_assembly {
+        opt.emit = EmitOption::Backend("s0".into());
+    }
+    if opt.output_binary {
+        opt.emit = EmitOption::Backend("o0".into());
+    }
+
+    match &opt.emit {
+        EmitOption::Backend(name) => match chigusa::backend::find_backend(name) {
+            Some(b) => Some(b),
+            None => {
+                let names: Vec<_> = chigusa::backend::backends()
+                    .iter()
+                    .map(|b| b.name())
+                    .collect();
+                log::error!(
+                    "Bad emit option. Allowed are: token, ast, ast-debug, callgraph, callgraph-dot, size-report, {}",
+                    names.join(", ")
+                );
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    }
+}
+
+fn run(input: &str, tree: &chigusa::c0::ast::Program) -> ! {
+    let o0 = match chigusa::minivm::Codegen::new(tree).compile() {
+        Ok(o0) => o0,
+        Err(e) => report_compile_error(input, &format!("{}", e.var), e.span),
+    };
+    let stdin = std::io::stdin();
+    let stdout = std::io::stdout();
+    let mut vm = chigusa::minivm::vm::MiniVM::new(&o0, stdin.lock(), stdout.lock());
+    match vm.run() {
+        Ok(code) => std::process::exit(code),
+        Err(e) => {
+            // * The logger writes to stdout, where it would mix into the
+            // * program's output
+            eprintln!("Runtime error: {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn debug(opt: &DebugOpts, input: &str, tree: &chigusa::c0::ast::Program) -> ! {
+    let (o0, info) = match chigusa::minivm::Codegen::new(tree).compile_with_debug() {
+        Ok(res) => res,
+        Err(e) => report_compile_error(input, &format!("{}", e.var), e.span),
+    };
+    let program_input: Box<dyn std::io::BufRead> = match &opt.program_input {
+        Some(f) => Box::new(std::io::BufReader::new(
+            File::open(f).expect("File does not exist!"),
+        )),
+        None => Box::new(std::io::empty()),
+    };
+    let vm = chigusa::minivm::vm::MiniVM::new(&o0, program_input, std::io::stdout());
+    let mut debugger = match chigusa::minivm::debug::Debugger::new(vm, &info, input) {
+        Ok(d) => d,
+        Err(e) => {
+            eprintln!("Runtime error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let stdin = std::io::stdin();
+    let mut out = std::io::stdout();
+    let mut line = String::new();
+    loop {
+        print!("(chigusa) ");
+        out.flush().expect("Failed to write");
+        line.clear();
+        if stdin.read_line(&mut line).expect("Failed to read") == 0 {
+            break;
+        }
+        if !debugger.command(&line, &mut out).expect("Failed to write") {
+            break;
+        }
+    }
+    std::process::exit(0)
+}
+
+fn build(
+    opt: &BuildOpts,
+    input: &str,
+    tree: &chigusa::c0::ast::Program,
+    backend: Option<Box<dyn chigusa::backend::Backend>>,
+) {
+    if opt.dump_cfg.is_some() && backend.is_none() {
+        log::error!("--dump-cfg needs a backend to emit with, like s0 or o0");
+        std::process::exit(1);
+    }
+
     if opt.emit == EmitOption::Ast {
-        write_output(&opt, tree);
+        write_text(opt, chigusa::c0::dump::dump(tree));
+        return;
+    }
+
+    if opt.emit == EmitOption::AstDebug {
+        write_output(opt, tree);
         return;
     }
 
-    let s0 = chigusa::minivm::Codegen::new(&tree).compile();
-    let s0 = match s0 {
+    if opt.emit == EmitOption::CallGraph || opt.emit == EmitOption::CallGraphDot {
+        let graph = chigusa::c0::callgraph::CallGraph::new(tree);
+        if opt.emit == EmitOption::CallGraph {
+            write_text(opt, graph);
+        } else {
+            write_text(opt, graph.to_dot());
+        }
+        return;
+    }
+
+    if opt.emit == EmitOption::SizeReport {
+        // * Sizes are taken from the same layout the o0 backend writes
+        let o0 = match chigusa::minivm::Codegen::new(tree).compile() {
+            Ok(o0) => o0,
+            Err(e) => report_compile_error(input, &format!("{}", e.var), e.span),
+        };
+        write_text(opt, o0.size_report());
+        return;
+    }
+
+    let backend = backend.expect("Every other emit option is handled above");
+    let res = match &opt.dump_cfg {
+        Some(_) => backend.compile_with_cfg(tree),
+        None => backend.compile(tree).map(|a| (a, None)),
+    };
+    let (artifact, cfgs) = match res {
         Ok(t) => t,
-        Err(e) => {
-            let mut input_lines = input.lines();
-            let err_des = format!("Compile error: {}", &e.var);
+        Err(e) => report_compile_error(input, &e.msg, e.span),
+    };
 
-            if let Some(span) = e.span {
-                err_disp::pretty_print_error(&mut input_lines, span, &err_des);
-            } else {
-                log::error!("{}", err_des);
+    if let Some(dir) = &opt.dump_cfg {
+        let cfgs = match cfgs {
+            Some(cfgs) => cfgs,
+            None => {
+                log::error!(
+                    "Backend {} has no control flow graph to dump",
+                    backend.name()
+                );
+                std::process::exit(1);
             }
-            std::process::exit(1);
+        };
+        create_dir_all(dir).expect("Failed to create CFG directory");
+        for (name, dot) in cfgs {
+            let mut f =
+                File::create(dir.join(format!("{}.dot", name))).expect("Failed to create CFG file");
+            write!(f, "{}", dot).expect("Failed to write file");
         }
-    };
+    }
 
-    if opt.emit == EmitOption::S0 {
-        let mut f = File::create(&opt.output_file).expect("Failed to create output file");
-        write!(f, "{}", s0).expect("Failed to write");
+    if opt.stdout && backend.is_text() {
+        artifact
+            .write_to(&mut std::io::stdout())
+            .expect("Failed to write");
     } else {
-        // Emit O0
         let mut f = File::create(&opt.output_file).expect("Failed to create output file");
-        s0.write_binary(&mut f).expect("Failed to write");
+        artifact.write_to(&mut f).expect("Failed to write");
     }
 }
 
-fn write_output<T>(opt: &ParserConfig, val: T)
+fn write_output<T>(opt: &BuildOpts, val: T)
 where
     T: std::fmt::Debug,
 {
@@ -95,3 +277,37 @@ where
         write!(f, "{:#?}", val).expect("Failed to write file");
     }
 }
+
+fn write_text<T>(opt: &BuildOpts, val: T)
+where
+    T: std::fmt::Display,
+{
+    if opt.stdout {
+        print!("{}", val);
+    } else {
+        let mut f = File::create(&opt.output_file).expect("Failed to create output file");
+        write!(f, "{}", val).expect("Failed to write file");
+    }
+}
+
+fn report_compile_error(input: &str, msg: &str, span: Option<chigusa::prelude::Span>) -> ! {
+    let err_des = format!("Compile error: {}", msg);
+    if let Some(span) = span {
+        err_disp::pretty_print_error(&mut input.lines(), span, &err_des);
+    } else {
+        log::error!("{}", err_des);
+    }
+    std::process::exit(1);
+}
+
+fn print_diagnostics(to_stderr: bool, input: &str, diags: &chigusa::diag::DiagSink) {
+    for diag in diags.drain() {
+        if to_stderr {
+            // * The logger writes to stdout too, so it can't be used here
+            eprintln!("{}", diag);
+        } else {
+            let err_des = format!("{}: {}", diag.severity, diag.msg);
+            err_disp::pretty_print_error(&mut input.lines(), diag.span, &err_des);
+        }
+    }
+}
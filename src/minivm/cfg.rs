@@ -0,0 +1,59 @@
+//! Graphviz dump of the control flow graph of a function.
+//!
+//! The graph is taken right before layout, so it shows what `finish` is
+//! about to arrange: blocks after merging and peephole optimization, with
+//! jumps drawn as edges instead of instructions.
+
+use super::codegen::{BasicBlock, BlockEndJump};
+use crate::prelude::*;
+use indexmap::IndexSet;
+use std::fmt::{self, Write};
+
+/// Render the blocks reachable from block 0 as a DOT digraph named `name`
+pub(super) fn to_dot(name: &str, bbs: &[Ptr<BasicBlock>]) -> String {
+    let mut s = String::new();
+    write_dot(&mut s, name, bbs).unwrap();
+    s
+}
+
+fn write_dot(s: &mut String, name: &str, bbs: &[Ptr<BasicBlock>]) -> fmt::Result {
+    writeln!(s, "digraph {:?} {{", name)?;
+    writeln!(s, "    node [shape=box, fontname=monospace];")?;
+
+    let mut seen = IndexSet::new();
+    let mut pending = vec![0];
+    while let Some(id) = pending.pop() {
+        if !seen.insert(id) {
+            continue;
+        }
+        let bb = bbs[id].borrow();
+
+        let mut label = format!("bb{}\\l", id);
+        for inst in bb.inst.inner() {
+            label.push_str(&escape(&format!("{}", inst)));
+            label.push_str("\\l");
+        }
+        writeln!(s, "    bb{} [label=\"{}\"];", id, label)?;
+
+        match bb.end {
+            BlockEndJump::Unconditional(z) => {
+                writeln!(s, "    bb{} -> bb{};", id, z)?;
+                pending.push(z);
+            }
+            BlockEndJump::Conditional { z, nz } => {
+                // * `JNe(nz)` comes first in the layout, then `Jmp(z)`
+                writeln!(s, "    bb{} -> bb{} [label=\"true\"];", id, nz)?;
+                writeln!(s, "    bb{} -> bb{} [label=\"false\"];", id, z)?;
+                pending.push(z);
+                pending.push(nz);
+            }
+            BlockEndJump::Return | BlockEndJump::Unknown => {}
+        }
+    }
+
+    writeln!(s, "}}")
+}
+
+fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -1,3 +1,4 @@
+use super::debug::*;
 use super::err::*;
 use super::instgen::*;
 use super::*;
@@ -9,6 +10,9 @@ use std::convert::TryInto;
 use std::iter::Iterator;
 const bytes_per_slot: u16 = 4;
 
+/// Most instructions global initializers may run at compile time
+const INIT_FUEL: u64 = 1 << 20;
+
 #[derive(Debug, Clone)]
 struct Data {
     typ: Ptr<ast::TypeDef>,
@@ -34,6 +38,19 @@ impl DataSink {
     }
 
     pub fn put_data(&mut self, name: &str, val: Data) -> Option<u16> {
+        // * Constants are shared by the whole module, so identical ones can
+        // * use the same slot no matter which function asked for them.
+        if let (true, Either::Left(c)) = (val.is_const, &val.init_val) {
+            let existing = self.map.values().position(|d| match &d.init_val {
+                Either::Left(e) => d.is_const && same_constant(c, e),
+                _ => false,
+            });
+            if let Some(idx) = existing {
+                log::debug!("Reusing constant {} for {}", idx, name);
+                return Some(idx as u16);
+            }
+        }
+
         if self.map.len() < u16::max_value() as usize {
             if self.map.contains_key(name) {
                 None
@@ -80,8 +97,45 @@ impl DataSink {
     }
 }
 
+/// Constants are the same if they are written identically into the binary
+fn same_constant(a: &Constant, b: &Constant) -> bool {
+    match (a, b) {
+        (Constant::Number(a), Constant::Number(b)) => a == b,
+        (Constant::Float(a), Constant::Float(b)) => a.to_bits() == b.to_bits(),
+        (Constant::String(a), Constant::String(b)) => a == b,
+        _ => false,
+    }
+}
+
 type BB = Ptr<BasicBlock>;
 
+/// A transformation of the basic blocks of a function before layout
+pub struct Pass {
+    pub name: &'static str,
+    run: fn(&mut FnCodegen<'_, '_>),
+}
+
+/// All passes, run in this order on every function. Add new passes here.
+pub static PASSES: &[Pass] = &[
+    Pass {
+        name: "peephole",
+        run: |f| f.peephole(),
+    },
+    Pass {
+        name: "fold_branches",
+        run: |f| f.fold_branches(),
+    },
+    Pass {
+        name: "merge_blocks",
+        run: |f| f.merge_blocks(),
+    },
+    // * Merging puts instructions of different blocks next to each other
+    Pass {
+        name: "cleanup_peephole",
+        run: |f| f.peephole(),
+    },
+];
+
 #[derive(Debug, Clone)]
 pub(super) struct BasicBlock {
     pub id: usize,
@@ -143,6 +197,11 @@ struct GlobalData {
     pub vars: LocalVars,
     pub consts: DataSink,
     pub fns: IndexMap<String, FunctionType>,
+    /// DOT control flow graph of each compiled function, if asked for
+    pub cfgs: Option<IndexMap<String, String>>,
+    /// Line table and variables of each compiled function, if asked for.
+    /// Optimization passes are skipped then, so the line tables stay exact.
+    pub debug: Option<IndexMap<String, FnDebug>>,
 }
 
 impl GlobalData {
@@ -151,6 +210,8 @@ impl GlobalData {
             vars: LocalVars::new(),
             consts: DataSink::new(),
             fns: IndexMap::new(),
+            cfgs: None,
+            debug: None,
         }
     }
 }
@@ -225,7 +286,27 @@ impl<'a> Codegen<'a> {
         }
     }
 
-    pub fn compile(mut self) -> CompileResult<O0> {
+    pub fn compile(self) -> CompileResult<O0> {
+        self.compile_inner().map(|(o0, ..)| o0)
+    }
+
+    /// Compile the program, also returning the control flow graph of every
+    /// function in Graphviz DOT, keyed by function name. The start code is
+    /// listed as `_start`.
+    pub fn compile_with_cfg(mut self) -> CompileResult<(O0, IndexMap<String, String>)> {
+        self.glob.cfgs = Some(IndexMap::new());
+        self.compile_inner().map(|(o0, cfgs, _)| (o0, cfgs))
+    }
+
+    /// Compile the program without optimizations, also returning where the
+    /// code of each statement starts and where each variable is stored.
+    pub fn compile_with_debug(mut self) -> CompileResult<(O0, DebugInfo)> {
+        self.glob.debug = Some(IndexMap::new());
+        self.compile_inner()
+            .map(|(o0, _, debug)| (o0, debug.unwrap_or_default()))
+    }
+
+    fn compile_inner(mut self) -> CompileResult<(O0, IndexMap<String, String>, Option<DebugInfo>)> {
         let decls = &self.prog.blk.scope;
         let decls = &*decls.borrow();
 
@@ -255,7 +336,17 @@ impl<'a> Codegen<'a> {
             }
         }
 
-        Ok(O0 {
+        let cfgs = self.glob.cfgs.take().unwrap_or_default();
+        let fns = &self.glob.fns;
+        let vars = &self.glob.vars;
+        let debug = self.glob.debug.take().map(|mut debug| DebugInfo {
+            functions: fns
+                .keys()
+                .map(|name| debug.swap_remove(name).unwrap_or_default())
+                .collect(),
+            globals: debug_vars(vars),
+        });
+        let mut o0 = O0 {
             version: 1,
             constants: self
                 .glob
@@ -271,7 +362,11 @@ impl<'a> Codegen<'a> {
                 ins: start_code.unwrap(),
             },
             functions: self.glob.fns.into_iter().map(|f| f.1.into()).collect(),
-        })
+        };
+        if o0.start_code.ins.iter().any(|i| matches!(i, Inst::Call(_))) {
+            o0.start_code.ins = eval_globals(&o0)?;
+        }
+        Ok((o0, cfgs, debug))
     }
 
     fn make_start(&mut self) -> CompileResult<InstSink> {
@@ -366,7 +461,45 @@ impl<'a> Codegen<'a> {
     }
 }
 
+/// Run the start code at compile time, so global initializers that call
+/// functions become plain pushes of the values they produce. Initializers
+/// must finish within `INIT_FUEL` instructions and may not print or scan.
+fn eval_globals(o0: &O0) -> CompileResult<Vec<Inst>> {
+    let mut vm = vm::MiniVM::new(o0, std::io::empty(), std::io::sink())
+        .with_fuel(INIT_FUEL)
+        .without_io();
+    match vm.run_start().map(<[u32]>::to_vec) {
+        Ok(slots) => Ok(slots.into_iter().map(|v| Inst::IPush(v as i32)).collect()),
+        Err(e) => {
+            let chain = vm.call_chain();
+            let msg = if chain.is_empty() {
+                e.to_string()
+            } else {
+                format!("{} in {}", e, chain.join(" -> "))
+            };
+            Err(CompileErrorVar::BadGlobalInit(msg).into())
+        }
+    }
+}
+
 /// Resolve all named types into their definitions, and strip function types' bodies
+/// Variables of `loc` for the debugger. Their names in `loc` carry the id
+/// of the declaring scope after a backtick.
+fn debug_vars(loc: &LocalVars) -> Vec<DebugVar> {
+    loc.def_map
+        .iter()
+        .filter_map(|(name, var)| {
+            let (name, scope) = name.rsplit_once('`')?;
+            Some(DebugVar {
+                name: name.into(),
+                scope: scope.parse().ok()?,
+                offset: var.offset,
+                kind: VarKind::of(&var.typ.borrow())?,
+            })
+        })
+        .collect()
+}
+
 fn resolve_ty(ty: &ast::TypeDef, scope: Ptr<ast::Scope>) -> ast::TypeDef {
     match ty {
         ast::TypeDef::NamedType(n) => {
@@ -562,6 +695,9 @@ pub(super) struct FnCodegen<'a, 'b> {
 
     name: &'b str,
 
+    /// Id of the program's root scope. Variables declared there are globals.
+    root_scope_id: usize,
+
     break_tgt: Vec<usize>,
 
     /// Data count, only for naming usage
@@ -573,7 +709,13 @@ pub(super) struct FnCodegen<'a, 'b> {
     sink_pool: DeqPool<'a, InstSink>,
 
     start_bb: BB,
+    /// Blocks ending in a self tail call. They pop the locals before
+    /// jumping back to `SNew`.
+    tail_calls: Vec<usize>,
     bbs: Vec<BB>,
+    /// Start of each statement as block id and offset into the block, kept
+    /// only for debug info. `ip` is filled in once blocks are laid out.
+    line_marks: Vec<(usize, usize, LineEntry)>,
 }
 
 /// Implementation for larger function, statement and expression structures
@@ -594,6 +736,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         FnCodegen {
             f,
             name,
+            root_scope_id: ctx.prog.blk.scope.borrow().id,
             ret_type,
             params,
             param_siz: 0,
@@ -605,7 +748,9 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             inst: None,
             sink_pool: DeqPool::new_with_reset(&InstSink::new, &InstSink::reset),
             start_bb: start_bb.cp(),
+            tail_calls: vec![],
             bbs: vec![start_bb],
+            line_marks: vec![],
         }
     }
 
@@ -637,11 +782,17 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
                 stack_size,
                 self.param_siz
             );
-            let param_siz = self.param_siz;
-            self.start_bb
-                .borrow_mut()
-                .inst
-                .prepend(Inst::SNew(stack_size - param_siz));
+            let locals = stack_size - self.param_siz;
+            self.start_bb.borrow_mut().inst.prepend(Inst::SNew(locals));
+            let start_id = self.start_bb.borrow().id;
+            for mark in self.line_marks.iter_mut().filter(|m| m.0 == start_id) {
+                mark.1 += 1;
+            }
+            if locals > 0 {
+                for &id in &self.tail_calls {
+                    self.bbs[id].borrow_mut().inst.push(Inst::PopN(locals));
+                }
+            }
         }
 
         Ok(())
@@ -659,7 +810,130 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         Ok((inst, loc))
     }
 
+    /// Merge each block into its predecessor when that predecessor ends with
+    /// an unconditional jump to it and no other reachable block jumps there.
+    ///
+    /// Merged blocks are left empty and unreachable, so `finish` never lays
+    /// them out.
+    fn merge_blocks(&mut self) {
+        loop {
+            // * Count predecessors among blocks reachable from the start
+            let mut preds = vec![0usize; self.bbs.len()];
+            let mut reachable = IndexSet::new();
+            let mut pending = vec![0];
+            while let Some(id) = pending.pop() {
+                if !reachable.insert(id) {
+                    continue;
+                }
+                match self.bbs[id].borrow().end {
+                    BlockEndJump::Unconditional(z) => {
+                        preds[z] += 1;
+                        pending.push(z);
+                    }
+                    BlockEndJump::Conditional { z, nz } => {
+                        preds[z] += 1;
+                        preds[nz] += 1;
+                        pending.push(z);
+                        pending.push(nz);
+                    }
+                    _ => {}
+                }
+            }
+
+            let target = reachable
+                .iter()
+                .find_map(|&id| match self.bbs[id].borrow().end {
+                    BlockEndJump::Unconditional(z) if z != id && z != 0 && preds[z] == 1 => {
+                        Some((id, z))
+                    }
+                    _ => None,
+                });
+
+            match target {
+                Some((pred, succ)) => {
+                    log::debug!("Merging BB {} into BB {}", succ, pred);
+                    let mut succ = self.bbs[succ].borrow_mut();
+                    let mut pred = self.bbs[pred].borrow_mut();
+                    pred.inst.append_all(&mut succ.inst);
+                    pred.end = std::mem::replace(&mut succ.end, BlockEndJump::Return);
+                }
+                None => break,
+            }
+        }
+    }
+
+    /// Turn conditional jumps on a constant into unconditional ones. The
+    /// branch not taken becomes unreachable and is never laid out.
+    ///
+    /// Runs after the peephole pass, which folds constant conditions down
+    /// to a single push.
+    fn fold_branches(&mut self) {
+        for bb in &self.bbs {
+            let mut bb = bb.borrow_mut();
+            if let BlockEndJump::Conditional { z, nz } = bb.end {
+                let cond = match bb.inst.inner().last() {
+                    Some(inst) => super::peephole::int_const(inst),
+                    None => None,
+                };
+                if let Some(cond) = cond {
+                    log::debug!("Folding branch of BB {} on constant {}", bb.id, cond);
+                    bb.inst.pop();
+                    bb.end = BlockEndJump::Unconditional(if cond != 0 { nz } else { z });
+                }
+            }
+        }
+    }
+
+    /// Report a block that falls off the end of a non-void function.
+    ///
+    /// Runs on the CFG as written, before `fold_branches` can make such a
+    /// block unreachable, so `if (1) return x;` alone is still an error.
+    fn check_returns(&self) -> CompileResult<()> {
+        if self.ret_type.borrow().is_unit() {
+            return Ok(());
+        }
+        let mut seen = IndexSet::new();
+        let mut pending = vec![0];
+        while let Some(id) = pending.pop() {
+            if !seen.insert(id) {
+                continue;
+            }
+            match self.bbs[id].borrow().end {
+                BlockEndJump::Unconditional(z) => pending.push(z),
+                BlockEndJump::Conditional { z, nz } => {
+                    pending.push(z);
+                    pending.push(nz);
+                }
+                BlockEndJump::Return => {}
+                BlockEndJump::Unknown => {
+                    return Err(compile_err(
+                        CompileErrorVar::ControlReachesEndOfNonVoidFunction,
+                        self.f.span,
+                    ))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Run the peephole rules on every basic block
+    fn peephole(&mut self) {
+        for bb in &self.bbs {
+            super::peephole::optimize(&mut bb.borrow_mut().inst.0);
+        }
+    }
+
     pub fn finish(&mut self) -> CompileResult<InstSink> {
+        self.check_returns()?;
+        if self.data.debug.is_none() {
+            for pass in PASSES {
+                log::debug!("Running pass {}", pass.name);
+                (pass.run)(self);
+            }
+        }
+        if let Some(cfgs) = &mut self.data.cfgs {
+            cfgs.insert(self.name.into(), super::cfg::to_dot(self.name, &self.bbs));
+        }
         log::debug!("Finished compiling. function is {:#?}", &self.bbs);
 
         let mut bb_start: IndexMap<usize, usize> = IndexMap::new();
@@ -777,6 +1051,20 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             }
         }
 
+        if let Some(debug) = &mut self.data.debug {
+            let mut lines: Vec<_> = self
+                .line_marks
+                .drain(..)
+                .filter_map(|(bb, offset, entry)| {
+                    let ip = bb_start.get(&bb)? + offset;
+                    Some(LineEntry { ip, ..entry })
+                })
+                .collect();
+            lines.sort_by_key(|l| l.ip);
+            let vars = debug_vars(&self.loc);
+            debug.insert(self.name.into(), FnDebug { lines, vars });
+        }
+
         Ok(inst)
     }
 
@@ -795,7 +1083,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         &mut self,
         name: &str,
         var: &ast::SymbolDef,
-        // if id == 0 then it's global variable
+        // if id == root_scope_id then it's global variable
         id: usize,
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<()> {
@@ -829,7 +1117,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
                         CompileErrorVar::VoidVariable(name.into()),
                         Some(*decl_span),
                     ))
-                } else if typ.is_fn() && self.f.scope.borrow().id != 0 {
+                } else if typ.is_fn() && self.f.scope.borrow().id != self.root_scope_id {
                     Err(compile_err(
                         CompileErrorVar::NestedFunctions(name.into()),
                         Some(*decl_span),
@@ -844,7 +1132,40 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         }
     }
 
+    /// Note for the debugger that code of the statement at `span` starts at
+    /// the end of `bb`. Statements without code, like declarations without
+    /// an initializer, give their place to the statement after them.
+    fn mark_line(&mut self, bb: &BB, span: Span, scope: &Ptr<ast::Scope>) {
+        if self.data.debug.is_none() {
+            return;
+        }
+        let mut scopes = vec![];
+        let mut cur = Some(scope.cp());
+        while let Some(s) = cur {
+            let s = s.borrow();
+            scopes.push(s.id);
+            cur = s.last.as_ref().map(|last| last.cp());
+        }
+        let bb = bb.borrow();
+        let line = span.start.ln + 1;
+        let entry = LineEntry {
+            ip: 0,
+            line,
+            scopes,
+        };
+        let mark = (bb.id, bb.inst.len(), entry);
+        match self.line_marks.last_mut() {
+            Some(last) if (last.0, last.1) == (mark.0, mark.1) => *last = mark,
+            _ => self.line_marks.push(mark),
+        }
+    }
+
     fn gen_stmt(&mut self, stmt: &ast::Stmt, bb: BB, scope: Ptr<ast::Scope>) -> CompileResult<BB> {
+        match &stmt.var {
+            // * Loops mark their condition instead, which runs every iteration
+            ast::StmtVariant::Block(_) | ast::StmtVariant::Empty | ast::StmtVariant::While(_) => {}
+            _ => self.mark_line(&bb, stmt.span, &scope),
+        }
         match &stmt.var {
             ast::StmtVariant::Expr(e) => {
                 {
@@ -939,8 +1260,8 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         let def = scope.borrow().find_def_depth(&i.name).unwrap();
 
         // Global var in global scope is also local var
-        let global_scope = self.f.scope.borrow().id == 0;
-        let is_local_var = def.1 != 0 || global_scope;
+        let global_scope = self.f.scope.borrow().id == self.root_scope_id;
+        let is_local_var = def.1 != self.root_scope_id || global_scope;
 
         if is_local_var {
             // Local variable
@@ -1024,6 +1345,8 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             Ok(Ptr::new(ast::TypeDef::Unit))
         } else {
             // Normal expressions
+            // * Operands are always evaluated left to right. Conversions
+            // * are patched into the separate sinks, not reordered.
             let mut lhs_op = self.sink_pool.get();
 
             let lhs = self.gen_expr(b.lhs.cp(), &mut lhs_op, scope.cp())?;
@@ -1101,7 +1424,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         let f_idx = func_entry.0 as u16;
         let f_ret_typ = func_entry.2.return_type.cp();
 
-        // Push each param into stack
+        // Push each param into stack, from left to right
         // * Rust complains about lifetimes here, so we'll just move everything into
         // * a vector for now. A little waste of memory, but hey it works.
         let params_pair_iter: Vec<_> = f
@@ -1284,6 +1607,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         bb: BB,
         scope: Ptr<ast::Scope>,
     ) -> CompileResult<BB> {
+        self.mark_line(&bb, i.cond.borrow().span, &scope);
         {
             // Condition
             let cond = i.cond.cp();
@@ -1295,6 +1619,7 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
         let (final_bb_id, final_bb) = self.new_bb();
         self.break_tgt.push(final_bb_id);
         let while_bb = self.gen_stmt(&*i.block.borrow(), while_bb, scope.cp())?;
+        self.mark_line(&while_bb, i.cond.borrow().span, &scope);
         {
             // Condition
             let cond = i.cond.cp();
@@ -1419,6 +1744,12 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
                 ))
                 .into());
             }
+            if let ast::ExprVariant::FunctionCall(call) = &e.borrow().var {
+                if call.func == self.name {
+                    return self.gen_tail_call(call, bb, scope);
+                }
+            }
+
             // * Non-void return:
             let mut bb = bb.borrow_mut();
             let inst = &mut bb.inst;
@@ -1447,6 +1778,61 @@ impl<'a, 'b> FnCodegen<'a, 'b> {
             Ok(dummy_bb)
         }
     }
+
+    /// Compile `return f(...)` inside `f` itself into storing the arguments
+    /// into the parameters and jumping back to the start of the function.
+    ///
+    /// The other locals are popped in `gen` once their size is known, so
+    /// `SNew` zeroes them again just like on a real call.
+    fn gen_tail_call(
+        &mut self,
+        call: &ast::FunctionCall,
+        bb: BB,
+        scope: Ptr<ast::Scope>,
+    ) -> CompileResult<BB> {
+        if call.params.len() != self.params.len() {
+            return Err(CompileErrorVar::ParamLengthMismatch.into());
+        }
+
+        let fn_scope = self.f.scope.cp();
+        let fn_scope = fn_scope.borrow();
+        let offsets = fn_scope
+            .defs
+            .keys()
+            .take(self.params.len())
+            .map(|name| {
+                let var = self.loc.get_var(&format!("{}`{}", name, fn_scope.id));
+                var.map(|v| v.offset as i32).ok_or_else(|| {
+                    CompileErrorVar::Error(format!("Unable to find parameter {}", name))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        {
+            let inst = &mut bb.borrow_mut().inst;
+
+            // * Every argument is evaluated before any parameter is
+            // * overwritten, as later arguments may read earlier parameters
+            let params: Vec<_> = self.params.iter().map(|p| p.cp()).collect();
+            for ((arg, typ), offset) in call.params.iter().zip(params).zip(&offsets) {
+                inst.push(Inst::LoadA(0, *offset));
+                let res = self.gen_expr(arg.cp(), inst, scope.cp())?;
+                conv(res, typ, inst)?;
+            }
+            // * Address and value pairs are stored from the top of the stack
+            for typ in self.params.iter().rev() {
+                store(typ.cp(), inst)?;
+            }
+        }
+
+        log::debug!("Self tail call in {} becomes a jump", self.name);
+        let mut bb = bb.borrow_mut();
+        bb.end = BlockEndJump::Unconditional(0);
+        self.tail_calls.push(bb.id);
+
+        let (_, dummy_bb) = self.new_bb();
+        Ok(dummy_bb)
+    }
 }
 
 impl ast::OpVar {
@@ -0,0 +1,305 @@
+//! A debugger stepping through o0 code by C0 source line.
+//!
+//! `Codegen::compile_with_debug` keeps the code unoptimized and records where
+//! each statement starts and which slot each variable lives in. `Debugger`
+//! runs the program on MiniVM and answers commands with that information.
+
+use super::vm::{MiniVM, VmError};
+use crate::c0::ast;
+use indexmap::IndexSet;
+use std::io::{self, BufRead, Write};
+
+/// Where the code of each statement and each variable ended up
+#[derive(Debug, Default, Clone)]
+pub struct DebugInfo {
+    /// In the order of the o0 function table
+    pub functions: Vec<FnDebug>,
+    pub globals: Vec<DebugVar>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct FnDebug {
+    /// Sorted by `ip`
+    pub lines: Vec<LineEntry>,
+    /// Parameters and locals. Shadowed names appear once per scope.
+    pub vars: Vec<DebugVar>,
+}
+
+/// The first instruction of a statement
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LineEntry {
+    pub ip: usize,
+    /// Starting from 1
+    pub line: usize,
+    /// Ids of the scopes the statement is in, innermost first
+    pub scopes: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DebugVar {
+    pub name: String,
+    /// Id of the scope declaring it
+    pub scope: usize,
+    /// Slot in the frame of its function, or of the start code for globals
+    pub offset: u32,
+    pub kind: VarKind,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum VarKind {
+    Int,
+    Char,
+    Double,
+}
+
+impl VarKind {
+    pub fn of(ty: &ast::TypeDef) -> Option<VarKind> {
+        match ty {
+            ast::TypeDef::Primitive(p) => Some(match p.var {
+                ast::PrimitiveTypeVar::Float => VarKind::Double,
+                ast::PrimitiveTypeVar::UnsignedInt if p.occupy_bytes == 1 => VarKind::Char,
+                _ => VarKind::Int,
+            }),
+            _ => None,
+        }
+    }
+}
+
+impl FnDebug {
+    /// The statement the instruction at `ip` belongs to
+    pub fn line_at(&self, ip: usize) -> Option<&LineEntry> {
+        self.lines.iter().take_while(|l| l.ip <= ip).last()
+    }
+
+    /// The statement whose code starts at `ip`
+    pub fn starting_at(&self, ip: usize) -> Option<&LineEntry> {
+        self.lines.iter().find(|l| l.ip == ip)
+    }
+}
+
+const HELP: &str = "\
+break <line>    Stop before the statement on <line> runs
+delete <line>   Remove the breakpoint on <line>
+step            Run to the next statement
+continue        Run to the next breakpoint, or to the end
+print <name>    Show the value of a variable
+locals          Show every variable in scope
+backtrace       Show the calls that led here
+quit            Leave the debugger";
+
+/// A debugging session of one program
+pub struct Debugger<'a, R, W>
+where
+    R: BufRead,
+    W: Write,
+{
+    vm: MiniVM<'a, R, W>,
+    info: &'a DebugInfo,
+    source: Vec<&'a str>,
+    breakpoints: IndexSet<usize>,
+    /// Set when the program ended, after which it can't be stepped
+    ended: bool,
+}
+
+impl<'a, R, W> Debugger<'a, R, W>
+where
+    R: BufRead,
+    W: Write,
+{
+    /// Debug the program `vm` runs, which was compiled from `source` with
+    /// `info`. The session starts before the first statement of `main`.
+    pub fn new(
+        mut vm: MiniVM<'a, R, W>,
+        info: &'a DebugInfo,
+        source: &'a str,
+    ) -> Result<Self, VmError> {
+        vm.enter_main()?;
+        Ok(Debugger {
+            vm,
+            info,
+            source: source.lines().collect(),
+            breakpoints: IndexSet::new(),
+            ended: false,
+        })
+    }
+
+    /// Run one command, writing the reply to `out`. Returns `false` once the
+    /// session is over, either by `quit` or because the program ended.
+    pub fn command(&mut self, input: &str, out: &mut dyn Write) -> io::Result<bool> {
+        let mut words = input.split_whitespace();
+        let (cmd, arg) = match words.next() {
+            Some(cmd) => (cmd, words.next()),
+            None => return Ok(true),
+        };
+        match cmd {
+            "break" | "b" => self.set_breakpoint(arg, out)?,
+            "delete" | "d" => match arg.and_then(|a| a.parse::<usize>().ok()) {
+                Some(line) if self.breakpoints.shift_remove(&line) => {
+                    writeln!(out, "Deleted the breakpoint on line {}", line)?
+                }
+                Some(line) => writeln!(out, "No breakpoint on line {}", line)?,
+                None => writeln!(out, "Usage: delete <line>")?,
+            },
+            "step" | "s" => self.resume(true, out)?,
+            "continue" | "c" => self.resume(false, out)?,
+            "print" | "p" => match arg {
+                Some(name) => self.print_var(name, out)?,
+                None => writeln!(out, "Usage: print <name>")?,
+            },
+            "locals" => self.print_locals(out)?,
+            "backtrace" | "bt" => self.print_backtrace(out)?,
+            "help" | "h" => writeln!(out, "{}", HELP)?,
+            "quit" | "q" => return Ok(false),
+            _ => writeln!(out, "Unknown command '{}', try `help`", cmd)?,
+        }
+        Ok(!self.ended)
+    }
+
+    fn set_breakpoint(&mut self, arg: Option<&str>, out: &mut dyn Write) -> io::Result<()> {
+        let line = match arg.and_then(|a| a.parse().ok()) {
+            Some(line) => line,
+            None => return writeln!(out, "Usage: break <line>"),
+        };
+        let exists = self
+            .info
+            .functions
+            .iter()
+            .any(|f| f.lines.iter().any(|l| l.line == line));
+        if exists {
+            self.breakpoints.insert(line);
+            writeln!(out, "Breakpoint on line {}", line)
+        } else {
+            writeln!(out, "No statement starts on line {}", line)
+        }
+    }
+
+    /// Run until a statement starts, or with `step` unset, until a statement
+    /// on a breakpoint starts
+    fn resume(&mut self, step: bool, out: &mut dyn Write) -> io::Result<()> {
+        loop {
+            let res = self.vm.step();
+            self.ended = !matches!(res, Ok(None));
+            if self.ended {
+                self.vm.flush()?;
+            }
+            match res {
+                Ok(Some(code)) => return writeln!(out, "Program exited with code {}", code),
+                Err(e) => return writeln!(out, "Runtime error: {}", e),
+                Ok(None) => {}
+            }
+
+            let (func, ip) = match self.vm.position() {
+                Some(pos) => pos,
+                None => continue,
+            };
+            let entry = match self.info.functions.get(func as usize) {
+                Some(f) => f.starting_at(ip),
+                None => None,
+            };
+            if let Some(entry) = entry {
+                if step || self.breakpoints.contains(&entry.line) {
+                    self.vm.flush()?;
+                    let name = self.vm.function_name(func);
+                    writeln!(out, "Stopped in {} on line {}", name, entry.line)?;
+                    return self.print_source(entry.line, out);
+                }
+            }
+        }
+    }
+
+    fn print_source(&self, line: usize, out: &mut dyn Write) -> io::Result<()> {
+        match self.source.get(line - 1) {
+            Some(text) => writeln!(out, "{:>5} | {}", line, text),
+            None => Ok(()),
+        }
+    }
+
+    /// The variables visible where the program stopped, innermost first,
+    /// each with whether it is a global
+    fn visible_vars(&self) -> Vec<(&'a DebugVar, bool)> {
+        let mut vars = Vec::new();
+        if let Some((func, ip)) = self.vm.position() {
+            let f = &self.info.functions[func as usize];
+            if let Some(entry) = f.line_at(ip) {
+                for scope in &entry.scopes {
+                    vars.extend(
+                        f.vars
+                            .iter()
+                            .filter(|v| v.scope == *scope)
+                            .map(|v| (v, false)),
+                    );
+                }
+            }
+        }
+        vars.extend(self.info.globals.iter().map(|v| (v, true)));
+        vars
+    }
+
+    /// The value of `var` as C0 would print it, or `None` if its slots are
+    /// not allocated yet
+    fn value(&self, var: &DebugVar, global: bool) -> Option<String> {
+        let slot = |off: u32| {
+            if global {
+                self.vm.global(off as usize)
+            } else {
+                self.vm.local(off as usize)
+            }
+        };
+        Some(match var.kind {
+            VarKind::Int => format!("{}", slot(var.offset)? as i32),
+            VarKind::Char => format!("{:?}", slot(var.offset)? as u8 as char),
+            VarKind::Double => {
+                let hi = slot(var.offset)? as u64;
+                let lo = slot(var.offset + 1)? as u64;
+                format!("{}", f64::from_bits(hi << 32 | lo))
+            }
+        })
+    }
+
+    fn print_var(&self, name: &str, out: &mut dyn Write) -> io::Result<()> {
+        let vars = self.visible_vars();
+        match vars.iter().find(|(v, _)| v.name == name) {
+            Some((var, global)) => match self.value(var, *global) {
+                Some(value) => writeln!(out, "{} = {}", name, value),
+                None => writeln!(out, "{} is not allocated yet", name),
+            },
+            None => writeln!(out, "No variable named {} here", name),
+        }
+    }
+
+    fn print_locals(&self, out: &mut dyn Write) -> io::Result<()> {
+        let mut seen = IndexSet::new();
+        for (var, global) in self.visible_vars() {
+            if !global && seen.insert(&var.name) {
+                match self.value(var, false) {
+                    Some(value) => writeln!(out, "{} = {}", var.name, value)?,
+                    None => writeln!(out, "{} is not allocated yet", var.name)?,
+                }
+            }
+        }
+        if seen.is_empty() {
+            writeln!(out, "No locals")?;
+        }
+        Ok(())
+    }
+
+    fn print_backtrace(&self, out: &mut dyn Write) -> io::Result<()> {
+        let frames = self.vm.call_stack();
+        for (depth, (func, ip)) in frames.iter().rev().enumerate() {
+            // * Outer frames are past their call, which belongs to the
+            // * statement before
+            let ip = if depth == 0 { *ip } else { ip - 1 };
+            let line = self
+                .info
+                .functions
+                .get(*func as usize)
+                .and_then(|f| f.line_at(ip));
+            let name = self.vm.function_name(*func);
+            match line {
+                Some(entry) => writeln!(out, "#{} {} on line {}", depth, name, entry.line)?,
+                None => writeln!(out, "#{} {}", depth, name)?,
+            }
+        }
+        Ok(())
+    }
+}
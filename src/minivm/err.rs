@@ -93,6 +93,8 @@ pub enum CompileErrorVar {
     NoTargetToBreak,
     FunctionMissingBody(String),
     NestedFunctions(String),
+    /// A global initializer that cannot be evaluated at compile time
+    BadGlobalInit(String),
 
     NotLValue(String),
     NotImplemented(String),
@@ -1,6 +1,9 @@
+mod cfg;
 pub mod codegen;
+pub mod debug;
 pub mod err;
 mod instgen;
+pub mod peephole;
 
 pub use chigusa_minivm::*;
 pub use codegen::*;
@@ -0,0 +1,138 @@
+//! Peephole optimization over the instructions of a basic block.
+//!
+//! Each rule looks at a fixed-size window of instructions and may replace
+//! it. Rules run on basic blocks before layout, when no jump instruction
+//! exists yet, so removing or inserting instructions never breaks a target.
+
+use super::Inst;
+
+/// A rewrite on a window of `len` instructions
+pub struct Rule {
+    pub name: &'static str,
+    pub len: usize,
+    /// Returns the replacement of the window, or `None` if it does not match
+    pub rewrite: fn(&[Inst]) -> Option<Vec<Inst>>,
+}
+
+/// All rules, tried in this order at every position. Add new rules here.
+pub static RULES: &[Rule] = &[
+    Rule {
+        name: "push_pop",
+        len: 2,
+        rewrite: |w| match w {
+            [Inst::IPush(_), Inst::Pop1]
+            | [Inst::CPush(_), Inst::Pop1]
+            | [Inst::LoadC(_), Inst::Pop1]
+            | [Inst::LoadA(..), Inst::Pop1]
+            | [Inst::Dup, Inst::Pop1]
+            | [Inst::Dup2, Inst::Pop2] => Some(vec![]),
+            _ => None,
+        },
+    },
+    Rule {
+        name: "load_pop",
+        len: 3,
+        rewrite: |w| match w {
+            [Inst::LoadA(..), Inst::ILoad, Inst::Pop1]
+            | [Inst::LoadA(..), Inst::ALoad, Inst::Pop1]
+            | [Inst::LoadA(..), Inst::DLoad, Inst::Pop2] => Some(vec![]),
+            _ => None,
+        },
+    },
+    Rule {
+        name: "identity_arith",
+        len: 2,
+        rewrite: |w| match w {
+            [Inst::IPush(0), Inst::IAdd]
+            | [Inst::IPush(0), Inst::ISub]
+            | [Inst::IPush(1), Inst::IMul]
+            | [Inst::IPush(1), Inst::IDiv] => Some(vec![]),
+            _ => None,
+        },
+    },
+    Rule {
+        name: "double_neg",
+        len: 2,
+        rewrite: |w| match w {
+            [Inst::INeg, Inst::INeg] | [Inst::DNeg, Inst::DNeg] => Some(vec![]),
+            _ => None,
+        },
+    },
+    Rule {
+        name: "neg_const",
+        len: 2,
+        rewrite: |w| match w {
+            [Inst::IPush(x), Inst::INeg] => Some(vec![Inst::IPush(x.wrapping_neg())]),
+            _ => None,
+        },
+    },
+    Rule {
+        name: "fold_int",
+        len: 3,
+        rewrite: |w| {
+            let (a, b) = (int_const(&w[0])?, int_const(&w[1])?);
+            // * Results must match the VM bit for bit, so everything wraps
+            let val = match w[2] {
+                Inst::IAdd => a.wrapping_add(b),
+                Inst::ISub => a.wrapping_sub(b),
+                Inst::IMul => a.wrapping_mul(b),
+                // * Dividing by zero is left to fail at run time
+                Inst::IDiv if b != 0 => a.wrapping_div(b),
+                Inst::ICmp => a.cmp(&b) as i32,
+                _ => return None,
+            };
+            Some(vec![Inst::IPush(val)])
+        },
+    },
+    Rule {
+        name: "fold_i2c",
+        len: 2,
+        rewrite: |w| match w {
+            [x, Inst::I2C] => Some(vec![Inst::IPush(int_const(x)? & 0xff)]),
+            _ => None,
+        },
+    },
+];
+
+/// The value an instruction pushes, if it is an integer constant
+pub fn int_const(inst: &Inst) -> Option<i32> {
+    match inst {
+        Inst::IPush(x) => Some(*x),
+        Inst::CPush(x) => Some(*x as i32),
+        _ => None,
+    }
+}
+
+/// Apply `rules` to `inst` until none matches. Returns the number of rewrites.
+pub fn optimize_with(inst: &mut Vec<Inst>, rules: &[Rule]) -> usize {
+    let mut count = 0;
+    let mut i = 0;
+    while i < inst.len() {
+        let rewritten = rules.iter().find_map(|rule| {
+            if i + rule.len > inst.len() {
+                return None;
+            }
+            (rule.rewrite)(&inst[i..i + rule.len]).map(|rep| (rule, rep))
+        });
+        match rewritten {
+            Some((rule, rep)) => {
+                log::trace!("Peephole {} at {}", rule.name, i);
+                inst.splice(i..i + rule.len, rep);
+                count += 1;
+                // * The rewrite may complete a pattern that starts earlier
+                i = i.saturating_sub(max_len(rules) - 1);
+            }
+            None => i += 1,
+        }
+    }
+    count
+}
+
+/// Apply all registered rules to `inst`. Returns the number of rewrites.
+pub fn optimize(inst: &mut Vec<Inst>) -> usize {
+    optimize_with(inst, RULES)
+}
+
+fn max_len(rules: &[Rule]) -> usize {
+    rules.iter().map(|r| r.len).max().unwrap_or(1)
+}
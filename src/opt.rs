@@ -1,3 +1,4 @@
+use chigusa::backend::DEFAULT_EMIT;
 use std::fs::*;
 use std::io::{Read, Write};
 use std::path::PathBuf;
@@ -25,36 +26,132 @@ A compiler that parses c0 grammar and compiles it into o0 binary format.
 
 C0: https://github.com/BUAA-SE-Compiling/c0-handbook
 O0: https://github.com/BUAA-SE-Compiling/c0-vm-standards
-"
+
+Without a subcommand, the options of `build` are accepted directly.
+",
+    global_settings = &[structopt::clap::AppSettings::DisableVersion]
 )]
 pub struct ParserConfig {
+    #[structopt(subcommand)]
+    pub cmd: Option<Command>,
+
+    #[structopt(flatten)]
+    pub build: BuildOpts,
+
+    // /// Use JIT compilation and run immediately.
+    // #[structopt(long)]
+    // pub jit: bool,
+    /// Same as the `run` subcommand
+    #[structopt(long)]
+    pub run: bool,
+
+    /// Prints version information
+    #[structopt(short = "V", long)]
+    pub version: bool,
+
+    /// With `--version`, also print the backends, passes and features compiled in
+    #[structopt(long, requires = "version")]
+    pub verbose: bool,
+}
+
+impl ParserConfig {
+    /// The subcommand to execute. Flat options are a `build`, or a `run`
+    /// with `--run`.
+    pub fn into_command(self) -> Command {
+        match self.cmd {
+            Some(cmd) => cmd,
+            None if self.run => Command::Run(self.build.input),
+            None => Command::Build(self.build),
+        }
+    }
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    /// Compile the input and write the result. This is the default.
+    Build(BuildOpts),
+
+    /// Run every check `build` does, and the lints, without writing any output.
+    /// Exits with 1 if the input has errors.
+    Check(InputOpts),
+
+    /// Compile to o0 and run it in the built-in VM instead of writing any output.
+    /// `scan` reads from stdin, so give the source as a file.
+    Run(InputOpts),
+
+    /// Compile to o0 and step through it by source line in the built-in VM.
+    /// Commands are read from stdin, so give the source as a file. Type `help`
+    /// at the prompt to list them.
+    Debug(DebugOpts),
+}
+
+impl Command {
+    pub fn input(&self) -> &InputOpts {
+        match self {
+            Command::Build(b) => &b.input,
+            Command::Check(i) | Command::Run(i) => i,
+            Command::Debug(d) => &d.input,
+        }
+    }
+}
+
+/// Options shared by every subcommand
+#[derive(StructOpt, Debug)]
+pub struct InputOpts {
     /// Input file. Defaults to stdin if no file were supplied.
     #[structopt(name = "file", parse(from_os_str))]
     pub input_file: Option<PathBuf>,
 
-    /// Output file.
-    #[structopt(short, long = "out", default_value = "out", parse(from_os_str))]
-    pub output_file: PathBuf,
+    /// Use a bundled example program as input. Allowed are: fib, primes, sort, matrix, reverse
+    #[structopt(long, conflicts_with = "file")]
+    pub example: Option<String>,
 
     /// Verbossity. Allowed values are: debug, trace, info, warn, error, off.
     #[structopt(short, long, default_value = "warn", parse(try_from_str = parse_verbosity))]
     pub verbosity: log::LevelFilter,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct DebugOpts {
+    #[structopt(flatten)]
+    pub input: InputOpts,
+
+    /// File for `scan` to read from. Without it, `scan` finds no input.
+    #[structopt(long = "stdin", value_name = "FILE", parse(from_os_str))]
+    pub program_input: Option<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct BuildOpts {
+    #[structopt(flatten)]
+    pub input: InputOpts,
 
-    /// Write result to stdout. Overwrites `output-file`. Only for `token`, `ast` and `s0` targets.
+    /// Output file.
+    #[structopt(short, long = "out", default_value = "out", parse(from_os_str))]
+    pub output_file: PathBuf,
+
+    /// Write result to stdout. Overwrites `output-file`. Only for text targets, i.e. everything but `o0`.
     #[structopt(long)]
     pub stdout: bool,
 
-    // /// Use JIT compilation and run immediately.
-    // #[structopt(long)]
-    // pub jit: bool,
-    /// The type of code to emit. Allowed are: token, ast, s0, o0
+    /// Also write the control flow graph of every function as `<DIR>/<function>.dot`.
+    /// Graphs show the basic blocks right before they are laid out.
+    #[structopt(long, value_name = "DIR", parse(from_os_str))]
+    pub dump_cfg: Option<PathBuf>,
+
+    /// The type of code to emit. Allowed are: token, ast, ast-debug, s0, o0, c, size-report, callgraph, callgraph-dot
     ///
     /// Emit result explanation:
     /// - Token: Direct result from lexer (tokenizer)
-    /// - AST: Abstract Syntax Tree, direct result from parser (analyzer)
+    /// - AST: Abstract Syntax Tree, direct result from parser (analyzer), as an indented s-expression with spans
+    /// - AST-debug: The same tree in Rust debug format
     /// - s0: C0 assembly file
     /// - o0: C0 binary file
-    #[structopt(long, default_value = "o0", parse(try_from_str = EmitOption::parse))]
+    /// - c: C89 source file
+    /// - size-report: Size in bytes of each section and function of the o0 file
+    /// - callgraph: Call graph with recursion and max call depth, as text
+    /// - callgraph-dot: Same call graph, in Graphviz DOT
+    #[structopt(long, default_value = DEFAULT_EMIT, parse(try_from_str = EmitOption::parse))]
     pub emit: EmitOption,
 
     /// Emit C0 assembly file, same as `--emit s0`
@@ -70,8 +167,12 @@ pub struct ParserConfig {
 pub enum EmitOption {
     Token,
     Ast,
-    S0,
-    O0,
+    AstDebug,
+    CallGraph,
+    CallGraphDot,
+    SizeReport,
+    /// Compile with the backend registered under this name
+    Backend(String),
 }
 
 impl EmitOption {
@@ -79,9 +180,12 @@ impl EmitOption {
         match s {
             "token" => Ok(EmitOption::Token),
             "ast" => Ok(EmitOption::Ast),
-            "s0" => Ok(EmitOption::S0),
-            "o0" => Ok(EmitOption::O0),
-            _ => Err("Bad emit option. Allowed are: token, ast, s0, o0"),
+            "ast-debug" => Ok(EmitOption::AstDebug),
+            "callgraph" => Ok(EmitOption::CallGraph),
+            "callgraph-dot" => Ok(EmitOption::CallGraphDot),
+            "size-report" => Ok(EmitOption::SizeReport),
+            // * Backend names are checked against the registry by the driver
+            backend => Ok(EmitOption::Backend(backend.into())),
         }
     }
 }
@@ -0,0 +1,104 @@
+use crate::c0::callgraph::*;
+use crate::c0::lexer::Lexer;
+use crate::c0::parser::*;
+
+fn graph(input: &str) -> CallGraph {
+    let lexer = Lexer::new(input.chars());
+    let mut parser = Parser::new(lexer);
+    let prog = parser.parse().expect("Input should parse");
+
+    CallGraph::new(&prog)
+}
+
+#[test]
+fn test_callgraph_recursion() {
+    let g = graph(
+        r#"
+int fact(int n) {
+    if (n <= 1) return 1;
+    return n * fact(n - 1);
+}
+int square(int n) {
+    return n * n;
+}
+int twice(int n) {
+    return square(n) + square(n);
+}
+int main() {
+    print(fact(twice(2)));
+    return 0;
+}
+    "#,
+    );
+
+    assert!(g.is_recursive("fact"));
+    assert!(!g.is_recursive("square"));
+    assert!(!g.is_recursive("main"));
+
+    assert_eq!(g.recursive_sets(), vec![vec!["fact".to_owned()]]);
+
+    assert_eq!(
+        g.max_depth_path("main"),
+        Some(vec![
+            "main".to_owned(),
+            "twice".to_owned(),
+            "square".to_owned()
+        ])
+    );
+}
+
+#[test]
+fn test_callgraph_output() {
+    let g = graph(include_str!("../../examples/fib.c"));
+
+    let text = format!("{}", g);
+    assert!(text.contains("fib -> fib [recursive]"), "{}", text);
+    assert!(text.contains("max depth (2): main -> fib"), "{}", text);
+
+    let dot = g.to_dot();
+    assert!(dot.starts_with("digraph callgraph {"), "{}", dot);
+    assert!(dot.contains("\"fib\" [color=red];"), "{}", dot);
+    assert!(dot.contains("\"main\" -> \"fib\" [style=bold];"), "{}", dot);
+}
+
+#[test]
+fn test_callgraph_path_follows_calls() {
+    // * C0 has no prototypes, so mutual recursion is built by hand. `b`
+    // * calls `deep`, but `a` only gets there through `b`.
+    let edges: &[(&str, &[&str])] = &[
+        ("deep", &[]),
+        ("a", &["b"]),
+        ("b", &["a", "deep"]),
+        ("main", &["a"]),
+    ];
+    let g = CallGraph {
+        calls: edges
+            .iter()
+            .map(|(f, callees)| {
+                (
+                    f.to_string(),
+                    callees.iter().map(|c| c.to_string()).collect(),
+                )
+            })
+            .collect(),
+    };
+    assert_eq!(
+        g.recursive_sets(),
+        vec![vec!["a".to_owned(), "b".to_owned()]]
+    );
+
+    let path = g.max_depth_path("main").unwrap();
+    assert_eq!(path, vec!["main", "a", "b", "deep"]);
+    assert_eq!(
+        g.max_depth_path("b"),
+        Some(vec!["b".to_owned(), "deep".to_owned()])
+    );
+
+    let dot = g.to_dot();
+    for (from, to) in &[("main", "a"), ("a", "b"), ("b", "deep")] {
+        let edge = format!("{:?} -> {:?} [style=bold];", from, to);
+        assert!(dot.contains(&edge), "{}", dot);
+    }
+    assert!(!dot.contains("\"b\" -> \"a\" [style=bold]"), "{}", dot);
+    assert!(!dot.contains("\"a\" -> \"deep\""), "{}", dot);
+}
@@ -0,0 +1,536 @@
+use crate::c0::lexer::Lexer;
+use crate::c0::parser::*;
+use crate::minivm::*;
+
+fn compile(input: &str) -> CompileResult<O0> {
+    let lexer = Lexer::new(input.chars());
+    let mut parser = Parser::new(lexer);
+    let prog = parser.parse().expect("Example should parse");
+
+    Codegen::new(&prog).compile()
+}
+
+pub(super) fn transpile(input: &str) -> crate::c89::CompileResult<String> {
+    let lexer = Lexer::new(input.chars());
+    let mut parser = Parser::new(lexer);
+    let prog = parser.parse().expect("Example should parse");
+
+    crate::c89::Codegen::new(&prog).compile()
+}
+
+#[test]
+fn test_example_fib_c89() {
+    let res = transpile(include_str!("../../examples/fib.c")).expect("Example should transpile");
+
+    assert!(res.starts_with("#include <stdio.h>"), "{}", res);
+    assert!(res.contains("int fib(int n) {"), "{}", res);
+    assert!(res.contains("scanf(\"%d\", &n);"), "{}", res);
+    assert!(res.contains("printf(\"%d %d\\n\", i, fib(i));"), "{}", res);
+}
+
+#[test]
+fn test_example_primes_c89() {
+    let res = transpile(include_str!("../../examples/primes.c")).expect("Example should transpile");
+
+    assert!(res.contains("int is_prime(int);"), "{}", res);
+    assert!(res.contains("while ("), "{}", res);
+}
+
+#[test]
+fn test_c89_sequenced_side_effects() {
+    let res = transpile(
+        r#"
+int x;
+int f(int a) {
+    x = x * 10 + a;
+    return a;
+}
+int main() {
+    int y;
+    y = f(1) && f(2) + f(3);
+    y = (f(4), f(5) + f(6));
+    return y;
+}
+"#,
+    )
+    .expect("Program should transpile");
+
+    // * The temporaries of the rhs of `&&` only run if the lhs holds, and
+    // * those of the rhs of `,` after its lhs
+    let expected = r#"    {
+        int c0_t1 = f(1) != 0;
+        if (c0_t1) {
+            int c0_t0 = f(2);
+            c0_t1 = (c0_t0 + f(3)) != 0;
+        }
+        y = c0_t1;
+    }
+    {
+        f(4);
+        {
+            int c0_t2 = f(5);
+            y = (c0_t2 + f(6));
+        }
+    }
+"#;
+    assert!(res.contains(expected), "{}", res);
+}
+
+#[test]
+fn test_c89_main_and_names() {
+    let res = transpile(
+        r#"
+int printf;
+double abs(double x) {
+    if (x < 0) return -x;
+    return x;
+}
+void main() {
+    int c0_t0 = 1;
+    printf = c0_t0;
+    if (printf) return;
+    print(abs(-1.5));
+}
+"#,
+    )
+    .expect("Program should transpile");
+
+    assert!(res.contains("int c0_printf;"), "{}", res);
+    assert!(res.contains("double c0_abs(double x) {"), "{}", res);
+    assert!(res.contains("printf(\"%f\\n\", c0_abs(-1.5));"), "{}", res);
+    assert!(res.contains("int c0_c0_t0;"), "{}", res);
+
+    // * A `void main` still has to exit with 0
+    assert!(res.contains("int main(void);"), "{}", res);
+    assert!(res.contains("int main(void) {"), "{}", res);
+    assert!(!res.contains("return;"), "{}", res);
+    assert!(res.ends_with("    return 0;\n}\n\n"), "{}", res);
+}
+
+#[test]
+fn test_backend_registry() {
+    use crate::backend::*;
+
+    let lexer = Lexer::new(include_str!("../../examples/fib.c").chars());
+    let prog = Parser::new(lexer).parse().expect("Example should parse");
+
+    for name in &["s0", "o0", "c"] {
+        let backend = find_backend(name).expect("Backend should be registered");
+        assert_eq!(backend.name(), *name);
+
+        let res = backend.compile(&prog).expect("Example should compile");
+        match res {
+            Artifact::Text(_) => assert!(backend.is_text()),
+            Artifact::Binary(_) => assert!(!backend.is_text()),
+        }
+    }
+
+    assert!(find_backend("arm").is_none());
+    // * Reports are emit options of their own, not code targets
+    assert!(find_backend("size-report").is_none());
+}
+
+#[test]
+fn test_merge_fallthrough_blocks() {
+    let res = compile(
+        r#"
+int f(int x) {
+    if (x) {
+        x = 1;
+    } else {
+        return 2;
+    }
+    return x;
+}
+int main() {
+    return f(1);
+}
+    "#,
+    )
+    .expect("Program should compile");
+
+    // * Only the conditional branch of `if` needs a jump; the true branch
+    // * falls through into the code after it.
+    let jumps = res.functions[0]
+        .ins
+        .iter()
+        .filter(|i| matches!(i, Inst::Jmp(_)))
+        .count();
+    assert_eq!(jumps, 1, "{:#?}", res.functions[0].ins);
+}
+
+#[test]
+fn test_constants_are_shared() {
+    let res = compile(
+        r#"
+void f() {
+    print("hello", 1.5);
+}
+void main() {
+    print("hello", 1.5, "main");
+    f();
+}
+    "#,
+    )
+    .expect("Program should compile");
+
+    // * "f", "main", "hello" and 1.5. The literal "main" reuses the name of
+    // * the function.
+    assert_eq!(res.constants.len(), 4, "{:#?}", res.constants);
+}
+
+const EVAL_ORDER: &str = r#"
+int x;
+int f(int a) {
+    x = x * 10 + a;
+    return a;
+}
+int main() {
+    int y;
+    y = f(1) - f(2);
+    print(f(3), f(4));
+    while (f(5) < f(6) + x) {
+        y = y + 1;
+    }
+    return y;
+}
+"#;
+
+#[test]
+fn test_eval_order() {
+    let res = compile(EVAL_ORDER).expect("Program should compile");
+
+    // * Arguments of the calls, in the order they are evaluated. The loop
+    // * condition is checked once more at the end of the loop.
+    let ins = &res.functions[1].ins;
+    let args: Vec<_> = ins
+        .windows(2)
+        .filter_map(|w| match w {
+            [Inst::IPush(a), Inst::Call(0)] => Some(*a),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(args, vec![1, 2, 3, 4, 5, 6, 5, 6], "{:#?}", ins);
+}
+
+#[test]
+fn test_eval_order_c89() {
+    let res = transpile(EVAL_ORDER).expect("Program should transpile");
+
+    let expected = r#"    {
+        int c0_t0 = f(1);
+        y = c0_t0 - f(2);
+    }
+    {
+        int c0_t1 = f(3);
+        printf("%d %d\n", c0_t1, f(4));
+    }
+    while (1) {
+        int c0_t2 = f(5);
+        int c0_t3 = f(6);
+        if (!(c0_t2 < (c0_t3 + x))) break;
+        {
+            y = y + 1;
+        }
+    }
+"#;
+    assert!(res.contains(expected), "{}", res);
+}
+
+#[test]
+fn test_size_report() {
+    let res = compile(include_str!("../../examples/fib.c")).expect("Example should compile");
+    let report = res.size_report();
+
+    let mut bin = Vec::new();
+    res.write_binary(&mut bin).unwrap();
+    assert_eq!(report.total(), bin.len());
+
+    // * The function table is its u16 length plus every function
+    let fns: usize = report.functions.iter().map(|f| f.1).sum();
+    assert_eq!(report.function_table, fns + 2);
+
+    let names: Vec<_> = report.functions.iter().map(|f| &f.0[..]).collect();
+    assert_eq!(names, vec!["fib", "main"]);
+    assert!(report.largest(1)[0].1 >= report.functions[0].1.max(report.functions[1].1));
+}
+
+#[test]
+fn test_build_info() {
+    let info = crate::build_info();
+
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    let names: Vec<_> = crate::backend::backends()
+        .iter()
+        .map(|b| b.name())
+        .collect();
+    assert_eq!(info.backends, names);
+    assert!(info.backends.contains(&info.default_emit));
+    assert_eq!(
+        info.passes,
+        vec![
+            "peephole",
+            "fold_branches",
+            "merge_blocks",
+            "cleanup_peephole"
+        ]
+    );
+
+    let text = format!("{}", info);
+    assert!(
+        text.starts_with(&format!("chigusa {}\n", info.version)),
+        "{}",
+        text
+    );
+}
+
+pub(super) fn run(input: &str, stdin: &str) -> (i32, String) {
+    let prog = compile(input).expect("Program should compile");
+    let mut out = Vec::new();
+    let code = crate::minivm::vm::MiniVM::new(&prog, stdin.as_bytes(), &mut out)
+        .run()
+        .expect("Program should run");
+    (code, String::from_utf8(out).unwrap())
+}
+
+#[test]
+fn test_examples_all_backends() {
+    for (name, src) in crate::examples::EXAMPLES {
+        let lexer = Lexer::new(src.chars());
+        let prog = Parser::new(lexer).parse().expect("Example should parse");
+        for backend in crate::backend::backends() {
+            if let Err(e) = backend.compile(&prog) {
+                panic!("{} fails on {}: {}", backend.name(), name, e);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_run_stack_overflow() {
+    let prog = compile("int f(int x) { return f(x + 1) + 1; }\nint main() { return f(0); }")
+        .expect("Program should compile");
+    let mut out = Vec::new();
+    let res = crate::minivm::vm::MiniVM::new(&prog, "".as_bytes(), &mut out).run();
+    assert!(
+        matches!(res, Err(crate::minivm::vm::VmError::StackOverflow)),
+        "{:?}",
+        res
+    );
+}
+
+#[test]
+fn test_run_globals_and_doubles() {
+    let (code, out) = run(
+        r#"
+int g = 3;
+const double half = 0.5;
+double scale(double x) {
+    return x * half;
+}
+int main() {
+    char c;
+    scan(c);
+    g = g * 2 - 1;
+    print("g =", g, c, scale(g));
+    return g;
+}
+"#,
+        "x",
+    );
+    assert_eq!(code, 5);
+    assert_eq!(out, "g = 5 x 2.500000\n");
+
+    let (_, out) = run(EVAL_ORDER, "");
+    assert_eq!(out, "3 4\n");
+}
+
+#[test]
+fn test_global_init_calls() {
+    let input = r#"
+int sq(int x) {
+    return x * x;
+}
+int a = 3;
+double d = 1.5;
+int b = sq(a) + 1;
+int main() {
+    print(a, b, d);
+    return 0;
+}
+"#;
+    let prog = compile(input).expect("Program should compile");
+    let hi = (1.5f64.to_bits() >> 32) as i32;
+    assert_eq!(
+        prog.start_code.ins,
+        [
+            Inst::IPush(3),
+            Inst::IPush(hi),
+            Inst::IPush(0),
+            Inst::IPush(10)
+        ]
+    );
+    assert_eq!(run(input, "").1, "3 10 1.500000\n");
+
+    let err = |input| match compile(input) {
+        Err(CompileError {
+            var: CompileErrorVar::BadGlobalInit(msg),
+            ..
+        }) => msg,
+        res => panic!("{:?}", res.map(|_| ())),
+    };
+    let spin = "int spin(int x) { while (1) { x = x + 1; } return x; }\n\
+                int f(int x) { return spin(x); }\n\
+                int a = f(1);\n\
+                int main() { return a; }";
+    assert_eq!(err(spin), "Ran out of fuel in f -> spin");
+    let noisy = "int noisy() { print(1); return 1; }\n\
+                 int a = noisy();\n\
+                 int main() { return a; }";
+    assert_eq!(err(noisy), "Instruction iprint has side effects in noisy");
+}
+
+#[test]
+fn test_dump_cfg() {
+    let input = r"
+int main() {
+    int i = 0;
+    while (i < 3) {
+        if (i == 1) print(i);
+        i = i + 1;
+    }
+    return i;
+}
+";
+    let lexer = Lexer::new(input.chars());
+    let prog = Parser::new(lexer).parse().expect("Example should parse");
+    let (o0, cfgs) = Codegen::new(&prog)
+        .compile_with_cfg()
+        .expect("Example should compile");
+    assert_eq!(format!("{}", o0), format!("{}", compile(input).unwrap()));
+
+    let names: Vec<_> = cfgs.keys().map(|k| &k[..]).collect();
+    assert_eq!(names, vec!["_start", "main"]);
+
+    let dot = &cfgs["main"];
+    assert!(dot.starts_with("digraph \"main\" {\n"), "{}", dot);
+    assert!(dot.ends_with("}\n"), "{}", dot);
+    // * The loop condition is checked before the loop and again at its end
+    assert_eq!(dot.matches("[label=\"true\"]").count(), 3, "{}", dot);
+    assert_eq!(dot.matches("[label=\"false\"]").count(), 3, "{}", dot);
+    assert!(dot.contains("bb0 [label=\"bb0\\l"), "{}", dot);
+    assert!(dot.contains("\\liret\\l"), "{}", dot);
+}
+
+#[test]
+fn test_fold_branches() {
+    let input = r"
+int main() {
+    int i = 2 * 3 + 1;
+    while (1) {
+        if (0) print(1);
+        break;
+    }
+    if (i > 0) if (1 < 2) print(i);
+    if (1 < 2) print(i + 1);
+    return -(4 - 1);
+}
+";
+    let o0 = compile(input).expect("Example should compile");
+    let main = &o0.functions[0].ins;
+    assert!(main.contains(&Inst::IPush(7)), "{:?}", main);
+    // * Only the condition on `i` is left
+    let jumps = main.iter().filter(|i| matches!(i, Inst::JNe(_))).count();
+    assert_eq!(jumps, 1, "{:?}", main);
+
+    assert_eq!(run(input, ""), (-3, "7\n8\n".into()));
+
+    // * Missing returns are found before folding hides them
+    for input in &[
+        "int f(int x) { if (1) return x; }\nint main() { return f(1); }",
+        "int f() { while (1) {} }\nint main() { return f(); }",
+    ] {
+        let err = compile(input).expect_err("Missing return should not compile");
+        assert!(
+            matches!(err.var, CompileErrorVar::ControlReachesEndOfNonVoidFunction),
+            "{:?}",
+            err
+        );
+    }
+}
+
+#[test]
+fn test_self_tail_call() {
+    let input = r"
+int sum(int n, int acc) {
+    if (n == 0) return acc;
+    return sum(n - 1, acc + n);
+}
+int fact(int n) {
+    if (n == 0) return 1;
+    return n * fact(n - 1);
+}
+int main() {
+    print(sum(10, 0), fact(5));
+    return sum(3, 0);
+}
+";
+    let o0 = compile(input).expect("Example should compile");
+    let calls = |f: usize, idx: u16| o0.functions[f].ins.contains(&Inst::Call(idx));
+    // * Only the call in `fact` is not in tail position
+    assert!(!calls(0, 0), "{:?}", o0.functions[0].ins);
+    assert!(calls(1, 1), "{:?}", o0.functions[1].ins);
+
+    assert_eq!(run(input, ""), (6, "55 120\n".into()));
+
+    // * `seen` is zero on entry to every real call, so it must be here too
+    let input = r"
+int count(int n, int acc) {
+    int seen;
+    acc = acc + seen;
+    seen = n;
+    if (n == 0) return acc;
+    return count(n - 1, acc);
+}
+int main() {
+    return count(5, 0);
+}
+";
+    let o0 = compile(input).expect("Example should compile");
+    assert!(!o0.functions[0].ins.contains(&Inst::Call(0)));
+    assert_eq!(run(input, ""), (0, "".into()));
+}
+
+#[test]
+fn test_check() {
+    let check = |input: &str| {
+        let lexer = Lexer::new(input.chars());
+        let prog = Parser::new(lexer).parse().expect("Example should parse");
+        crate::backend::check(&prog)
+    };
+
+    assert!(check(include_str!("../../examples/fib.c")).is_ok());
+
+    let args = "int f(int x) { return x; }\nint main() { return f(1, 2); }";
+    assert!(check(args).is_err());
+    assert!(compile(args).is_err());
+
+    let void = "void g() {}\nint main() { int x; x = g(); return x; }";
+    assert!(check(void).is_err());
+    assert!(compile(void).is_err());
+}
+
+#[test]
+fn test_backend_cfg() {
+    let lexer = Lexer::new(include_str!("../../examples/fib.c").chars());
+    let prog = Parser::new(lexer).parse().expect("Example should parse");
+
+    let s0 = crate::backend::find_backend("s0").unwrap();
+    let (artifact, cfgs) = s0.compile_with_cfg(&prog).expect("Example should compile");
+    assert_eq!(artifact, s0.compile(&prog).unwrap());
+    let names: Vec<_> = cfgs.unwrap().keys().cloned().collect();
+    assert_eq!(names, vec!["_start", "fib", "main"]);
+
+    let c = crate::backend::find_backend("c").unwrap();
+    assert_eq!(c.compile_with_cfg(&prog).unwrap().1, None);
+}
@@ -0,0 +1,127 @@
+use crate::c0::lexer::Lexer;
+use crate::c0::parser::*;
+use crate::minivm::debug::*;
+use crate::minivm::*;
+
+const PROGRAM: &str = r#"int g = 7;
+int sq(int x) {
+    int y;
+    y = x * x;
+    return y;
+}
+int main() {
+    int a;
+    scan(a);
+    {
+        double a;
+        a = 1.5;
+        print(a);
+    }
+    while (a < 5) {
+        a = a + 1;
+    }
+    print(sq(a), g);
+    return 0;
+}
+"#;
+
+fn compile(input: &str) -> (O0, DebugInfo) {
+    let lexer = Lexer::new(input.chars());
+    let mut parser = Parser::new(lexer);
+    let prog = parser.parse().expect("Input should parse");
+
+    Codegen::new(&prog)
+        .compile_with_debug()
+        .expect("Input should compile")
+}
+
+/// Run `commands` one per line, returning what the debugger and the program
+/// wrote
+fn session(input: &str, stdin: &str, commands: &str) -> (String, String) {
+    let (o0, info) = compile(input);
+    let mut out = Vec::new();
+    let mut replies = Vec::new();
+    {
+        let vm = vm::MiniVM::new(&o0, stdin.as_bytes(), &mut out);
+        let mut debugger = Debugger::new(vm, &info, input).expect("main should exist");
+        for line in commands.lines() {
+            if !debugger.command(line, &mut replies).unwrap() {
+                break;
+            }
+        }
+    }
+    (
+        String::from_utf8(replies).unwrap(),
+        String::from_utf8(out).unwrap(),
+    )
+}
+
+#[test]
+fn test_debug_line_table() {
+    let (o0, info) = compile(PROGRAM);
+
+    // * `int y;` has no code, so line 3 is missing
+    let lines: Vec<_> = info.functions[0].lines.iter().map(|l| l.line).collect();
+    assert_eq!(lines, vec![4, 5]);
+    // * The loop condition runs before the loop and again after its body
+    let lines: Vec<_> = info.functions[1].lines.iter().map(|l| l.line).collect();
+    assert_eq!(lines, vec![9, 12, 13, 15, 16, 15, 18, 19]);
+    for (f, debug) in o0.functions.iter().zip(&info.functions) {
+        assert!(debug.lines.iter().all(|l| l.ip < f.ins.len()));
+    }
+
+    let globals: Vec<_> = info.globals.iter().map(|v| &v.name[..]).collect();
+    assert_eq!(globals, vec!["g"]);
+}
+
+#[test]
+fn test_debug_session() {
+    let commands = "\
+break 4
+break 3
+step
+step
+print a
+step
+print a
+step
+print a
+locals
+continue
+print x
+print g
+print a
+backtrace
+delete 4
+continue
+print a";
+    let (replies, out) = session(PROGRAM, "3", commands);
+    // * The session ends with the program, so the last command never runs
+    let expected = "\
+Breakpoint on line 4
+No statement starts on line 3
+Stopped in main on line 9
+    9 |     scan(a);
+Stopped in main on line 12
+   12 |         a = 1.5;
+a = 0
+Stopped in main on line 13
+   13 |         print(a);
+a = 1.5
+Stopped in main on line 15
+   15 |     while (a < 5) {
+a = 3
+a = 3
+Stopped in sq on line 4
+    4 |     y = x * x;
+x = 5
+g = 7
+No variable named a here
+#0 sq on line 4
+#1 main on line 18
+Deleted the breakpoint on line 4
+Program exited with code 0
+";
+    assert_eq!(replies, expected);
+    assert_eq!(out, "1.500000\n25 7\n");
+}
@@ -0,0 +1,54 @@
+use crate::diag::*;
+use crate::prelude::*;
+use std::sync::Arc;
+
+fn span_at(ln: usize, pos: usize) -> Span {
+    let p = Pos::new(ln, pos, ln * 100 + pos);
+    Span::from(p, p.inc())
+}
+
+#[test]
+fn test_diag_sorted_by_span() {
+    let sink = DiagSink::new();
+    sink.warn(span_at(3, 0), "third");
+    sink.error(span_at(1, 4), "second");
+    sink.warn(span_at(1, 2), "first");
+
+    assert!(sink.has_errors());
+    let msgs: Vec<_> = sink.sorted().into_iter().map(|d| d.msg).collect();
+    assert_eq!(msgs, vec!["first", "second", "third"]);
+
+    assert_eq!(sink.drain().len(), 3);
+    assert!(sink.is_empty());
+}
+
+#[test]
+fn test_diag_concurrent_report() {
+    let sink = Arc::new(DiagSink::new());
+
+    let handles: Vec<_> = (0..4)
+        .map(|t| {
+            let sink = sink.clone();
+            std::thread::spawn(move || {
+                for i in 0..25 {
+                    sink.warn(span_at(i * 4 + t, 0), format!("{}", i * 4 + t));
+                }
+            })
+        })
+        .collect();
+    for h in handles {
+        h.join().unwrap();
+    }
+
+    let msgs: Vec<_> = sink.drain().into_iter().map(|d| d.msg).collect();
+    let expected: Vec<_> = (0..100).map(|i| format!("{}", i)).collect();
+    assert_eq!(msgs, expected);
+}
+
+#[test]
+fn test_diag_display_line_col() {
+    let sink = DiagSink::new();
+    sink.warn(span_at(1, 4), "unused variable `a`");
+    let diag = sink.drain().pop().unwrap();
+    assert_eq!(format!("{}", diag), "2:5: Warning: unused variable `a`");
+}
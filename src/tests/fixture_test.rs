@@ -0,0 +1,194 @@
+use super::compiler_test::{run, transpile};
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// A program from `test_progs/` or `examples/` together with the expectations
+/// written at its end as `//==` comment blocks:
+///
+/// ```text
+/// //== stdin:
+/// // 10 2
+/// //== expect-stdout:
+/// // 1024
+/// //== expect-exitcode: 0
+/// ```
+///
+/// The lines of a block are the comment lines following its header, with the
+/// leading `//` and at most one space removed. `//== skip-c89: <reason>` keeps
+/// a fixture out of the comparison against gcc.
+#[derive(Debug, Default)]
+pub(super) struct Fixture {
+    pub name: String,
+    pub src: String,
+    pub stdin: String,
+    pub stdout: Option<String>,
+    pub exit_code: Option<i32>,
+    pub skip_c89: Option<String>,
+}
+
+impl Fixture {
+    pub fn parse(name: &str, src: &str) -> Fixture {
+        let mut fixture = Fixture {
+            name: name.into(),
+            src: src.into(),
+            ..Fixture::default()
+        };
+        let mut block: Option<&mut String> = None;
+        for line in src.lines() {
+            if let Some(header) = line.strip_prefix("//==") {
+                let header = header.trim();
+                block = match header {
+                    "stdin:" => Some(&mut fixture.stdin),
+                    "expect-stdout:" => Some(fixture.stdout.get_or_insert_with(String::new)),
+                    _ => {
+                        if let Some(code) = header.strip_prefix("expect-exitcode:") {
+                            fixture.exit_code = Some(code.trim().parse().unwrap_or_else(|_| {
+                                panic!("{}: bad exit code '{}'", name, code.trim())
+                            }));
+                        } else if let Some(reason) = header.strip_prefix("skip-c89:") {
+                            fixture.skip_c89 = Some(reason.trim().into());
+                        } else {
+                            panic!("{}: unknown block '{}'", name, header);
+                        }
+                        None
+                    }
+                };
+            } else if let (Some(text), Some(buf)) = (line.strip_prefix("//"), block.as_mut()) {
+                buf.push_str(text.strip_prefix(' ').unwrap_or(text));
+                buf.push('\n');
+            } else {
+                block = None;
+            }
+        }
+        fixture
+    }
+
+    pub fn has_expectations(&self) -> bool {
+        self.stdout.is_some() || self.exit_code.is_some()
+    }
+}
+
+/// Every fixture that states at least one expectation, sorted by path.
+pub(super) fn fixtures() -> Vec<Fixture> {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let mut paths: Vec<_> = ["test_progs", "examples"]
+        .iter()
+        .flat_map(|dir| std::fs::read_dir(root.join(dir)).expect("fixture dir should exist"))
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    paths.sort();
+    paths
+        .iter()
+        .filter_map(|path| {
+            let src = std::fs::read_to_string(path).ok()?;
+            let name = path.strip_prefix(root).unwrap().display().to_string();
+            Some(Fixture::parse(&name, &src)).filter(Fixture::has_expectations)
+        })
+        .collect()
+}
+
+/// The exit status a process returning `code` reports to the shell.
+pub(super) fn exit_status(code: i32) -> i32 {
+    code & 0xff
+}
+
+#[test]
+fn test_parse_fixture() {
+    let fixture = Fixture::parse(
+        "t.c",
+        "int main() { return 0; }\n//== stdin:\n// 1 2\n//== expect-stdout:\n//  a\n//\n//== expect-exitcode: 3\n//== skip-c89: why\n",
+    );
+    assert_eq!(fixture.stdin, "1 2\n");
+    assert_eq!(fixture.stdout.as_deref(), Some(" a\n\n"));
+    assert_eq!(fixture.exit_code, Some(3));
+    assert_eq!(fixture.skip_c89.as_deref(), Some("why"));
+
+    let plain = Fixture::parse("u.c", "// just a comment\nint main() { return 0; }\n");
+    assert!(!plain.has_expectations());
+}
+
+#[test]
+fn test_fixture_expectations() {
+    let fixtures = fixtures();
+    assert!(
+        fixtures.len() >= 19,
+        "only {} fixtures found",
+        fixtures.len()
+    );
+    for fixture in &fixtures {
+        let (code, out) = run(&fixture.src, &fixture.stdin);
+        if let Some(stdout) = &fixture.stdout {
+            assert_eq!(&out, stdout, "stdout of {}", fixture.name);
+        }
+        if let Some(exit_code) = fixture.exit_code {
+            assert_eq!(
+                exit_status(code),
+                exit_code,
+                "exit code of {}",
+                fixture.name
+            );
+        }
+    }
+}
+
+/// Runs a native executable on `stdin`, returning its exit status and stdout.
+fn run_native(exe: &Path, stdin: &str) -> (i32, String) {
+    let mut child = Command::new(exe)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Program should start");
+    // Programs that never scan may exit before reading their input.
+    let _ = child.stdin.take().unwrap().write_all(stdin.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    let code = output.status.code().expect("Program should exit normally");
+    (code, String::from_utf8(output.stdout).unwrap())
+}
+
+#[test]
+fn test_fixtures_match_gcc() {
+    if Command::new("gcc").arg("--version").output().is_err() {
+        eprintln!("gcc not found, skipping the comparison against gcc");
+        return;
+    }
+    let dir = std::env::temp_dir().join(format!("chigusa-golden-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+
+    let mut compared = 0;
+    for fixture in fixtures().iter().filter(|f| f.skip_c89.is_none()) {
+        let c = transpile(&fixture.src)
+            .unwrap_or_else(|e| panic!("{} should transpile: {}", fixture.name, e));
+        let stem = fixture
+            .name
+            .replace(|c: char| !c.is_ascii_alphanumeric(), "_");
+        let (src, exe) = (dir.join(format!("{}.c", stem)), dir.join(&stem));
+        std::fs::write(&src, c).unwrap();
+        let gcc = Command::new("gcc")
+            .args(&["-std=c89", "-pedantic-errors", "-o"])
+            .arg(&exe)
+            .arg(&src)
+            .output()
+            .unwrap();
+        assert!(
+            gcc.status.success(),
+            "gcc rejects {}:\n{}",
+            fixture.name,
+            String::from_utf8_lossy(&gcc.stderr)
+        );
+
+        let (code, out) = run(&fixture.src, &fixture.stdin);
+        let (native_code, native_out) = run_native(&exe, &fixture.stdin);
+        assert_eq!(out, native_out, "stdout of {} under gcc", fixture.name);
+        assert_eq!(
+            exit_status(code),
+            native_code,
+            "exit code of {} under gcc",
+            fixture.name
+        );
+        compared += 1;
+    }
+
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(compared >= 18, "only {} fixtures compared", compared);
+}
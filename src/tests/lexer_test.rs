@@ -109,6 +109,18 @@ scan
     assert_eq!(vars, expected);
 }
 
+#[test]
+fn test_lex_line_comments() {
+    let src = "// one\n// two\nif\r\n// three\r\nelse // four";
+
+    let lexer = Lexer::new(src.chars());
+
+    let vars: Vec<_> = lexer.map(|token| token.var).collect();
+
+    use TokenType::*;
+    assert_eq!(vars, [If, Else]);
+}
+
 #[test]
 fn test_lex_ops() {
     let src = r#"
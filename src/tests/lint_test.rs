@@ -0,0 +1,48 @@
+use crate::c0::lexer::Lexer;
+use crate::c0::lint::*;
+use crate::c0::parser::*;
+use crate::diag::*;
+
+fn lint_msgs(input: &str) -> Vec<(usize, String)> {
+    let lexer = Lexer::new(input.chars());
+    let mut parser = Parser::new(lexer);
+    let prog = parser.parse().expect("Input should parse");
+
+    let sink = DiagSink::new();
+    lint(&prog, &sink);
+    sink.drain()
+        .into_iter()
+        .map(|d| (d.span.start.ln, d.msg))
+        .collect()
+}
+
+#[test]
+fn test_lint_unused_result() {
+    let res = lint_msgs(
+        r#"
+int f(int x) {
+    return x;
+}
+int main() {
+    int a = 1, b = 2;
+    a + b;
+    a == 1;
+    while (a) {
+        (a * 2);
+    }
+    f(a);
+    a = b + 1;
+    a++;
+    (a = 3) + 1;
+    f(1) + 2;
+    return 0;
+}
+    "#,
+    );
+
+    let lines: Vec<_> = res.iter().map(|(ln, _)| *ln).collect();
+    assert_eq!(lines, vec![6, 7, 9], "{:?}", res);
+    assert!(res
+        .iter()
+        .all(|(_, msg)| msg == "Result of expression is unused"));
+}
@@ -1,3 +1,9 @@
+mod callgraph_test;
 mod compiler_test;
+mod debug_test;
+mod diag_test;
+mod fixture_test;
 mod lexer_test;
+mod lint_test;
 mod parser_test;
+mod peephole_test;
@@ -184,3 +184,96 @@ void main()}
         );
     }
 }
+
+#[test]
+fn test_truncated_inputs() {
+    let inputs = [
+        "int main() {",
+        "int main() { int x = 1 +",
+        "int main() { int x = (1 + 2",
+        "int main() { x",
+        "int f(int a,",
+        "int f(int a, int",
+        "int main() { print(1,",
+        "int main() { print(1 2); }",
+        "int main() { while (1",
+        "int main() { if (1) {",
+        "int main() { return 1",
+        "int f(int a) { return 0; } int main() { f(1,",
+        "int x = 1 1;",
+        "int x = ;",
+    ];
+
+    // * The loops that parse these all stop on their own, so the progress
+    // * check never has to step in
+    for input in inputs.iter() {
+        match parse(input) {
+            Ok(_) => panic!("'{}' does not result in error!", input),
+            Err(e) => assert!(
+                !matches!(&e.var, ParseErrVariant::InternalErr(msg) if msg.contains("no progress")),
+                "'{}' results in {:?}",
+                input,
+                e
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_ast_dump() {
+    let input = r#"int x = 1;
+int f(int a) {
+    int b;
+    b = -a;
+    return b;
+}
+"#;
+
+    let expected = r#"(program
+  (global int x @1:5-1:10)
+  (fn f -> int @2:6-2:13
+    (param int a @2:11-2:12)
+    (block @2:14-6:2
+      (let int b @3:9-3:10)
+      (expr @4:5-4:11
+        (binary = @4:5-4:11
+          (ident b @4:5-4:6)
+          (unary - @4:9-4:11
+            (ident a @4:10-4:11))))
+      (return @5:12-5:13
+        (ident b @5:12-5:13))))
+  (exprs @1:1-1:10
+    (binary = @1:5-1:10
+      (ident x @1:5-1:6)
+      (int 1 @1:9-1:10))))
+"#;
+
+    let first = crate::c0::dump::dump(&parse(input).unwrap());
+    assert_eq!(first, expected);
+
+    // * Scope ids differ between parses, the dump must not
+    let second = crate::c0::dump::dump(&parse(input).unwrap());
+    assert_eq!(first, second);
+}
+
+#[test]
+fn test_builtin_redeclaration() {
+    let inputs = [
+        "int int;",
+        "double char = 1.0;",
+        "int void() { return 0; }",
+        "int f(int double) { return 0; }",
+        "int f(int a, char int) { return 0; }",
+        "void main() { int x; { double int; } }",
+    ];
+
+    for input in inputs.iter() {
+        match parse(input) {
+            Err(ParseError {
+                var: ParseErrVariant::BuiltinRedeclaration(_),
+                ..
+            }) => {}
+            res => panic!("'{}' should be rejected, got {:?}", input, res.map(|_| ())),
+        }
+    }
+}
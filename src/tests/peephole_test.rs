@@ -0,0 +1,75 @@
+use crate::minivm::peephole::*;
+use crate::minivm::Inst::*;
+
+#[test]
+fn test_peephole_rules() {
+    let mut inst = vec![
+        SNew(1),
+        IPush(3),
+        Pop1,
+        LoadA(0, 0),
+        ILoad,
+        IPush(0),
+        IAdd,
+        IPush(1),
+        IMul,
+        IRet,
+    ];
+    assert_eq!(optimize(&mut inst), 3);
+    assert_eq!(inst, vec![SNew(1), LoadA(0, 0), ILoad, IRet]);
+}
+
+#[test]
+fn test_peephole_cascade() {
+    // * Removing the inner pair makes the outer one adjacent
+    let mut inst = vec![LoadA(0, 0), IPush(2), INeg, INeg, Pop1, Pop1];
+    optimize(&mut inst);
+    assert_eq!(inst, vec![]);
+}
+
+#[test]
+fn test_peephole_custom_rules() {
+    let rules = [Rule {
+        name: "nop",
+        len: 1,
+        rewrite: |w| match w {
+            [Nop] => Some(vec![]),
+            _ => None,
+        },
+    }];
+    let mut inst = vec![Nop, IPush(1), Nop, IPrint];
+    assert_eq!(optimize_with(&mut inst, &rules), 2);
+    assert_eq!(inst, vec![IPush(1), IPrint]);
+}
+
+#[test]
+fn test_peephole_fold_int() {
+    // * (2 * 3 + 1 < 8) folds all the way down
+    let mut inst = vec![
+        IPush(2),
+        IPush(3),
+        IMul,
+        IPush(1),
+        IAdd,
+        IPush(8),
+        ICmp,
+        IRet,
+    ];
+    optimize(&mut inst);
+    assert_eq!(inst, vec![IPush(-1), IRet]);
+
+    let mut inst = vec![
+        IPush(i32::max_value()),
+        IPush(1),
+        IAdd,
+        CPush(0x41),
+        IPush(0x100),
+        IAdd,
+        I2C,
+    ];
+    optimize(&mut inst);
+    assert_eq!(inst, vec![IPush(i32::min_value()), IPush(0x41)]);
+
+    let mut inst = vec![IPush(1), IPush(0), IDiv];
+    assert_eq!(optimize(&mut inst), 0);
+}
@@ -3,3 +3,7 @@ char c = 'c';
 void main() {
   print(c);
 }
+
+//== expect-stdout:
+// c
+//== expect-exitcode: 0
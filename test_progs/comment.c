@@ -15,3 +15,7 @@ int main() {
   print(mod(3, 2));
   return 0;
 }
+
+//== expect-stdout:
+// 1
+//== expect-exitcode: 0
@@ -3,3 +3,7 @@ int main() {
   print((int)c);
   return 0;
 }
+
+//== expect-stdout:
+// 1
+//== expect-exitcode: 0
@@ -12,3 +12,9 @@ int main() {
   print(result);
   return 0;
 }
+
+//== stdin:
+// 10 2
+//== expect-stdout:
+// 1024
+//== expect-exitcode: 0
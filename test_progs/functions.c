@@ -17,3 +17,13 @@ int main() {
   }
   return 0;
 }
+
+//== stdin:
+// 5
+//== expect-stdout:
+// 8
+// 13
+// 21
+// 34
+// 55
+//== expect-exitcode: 0
@@ -7,3 +7,11 @@ void main(){
     print(114514);
     print(true);
 }
+
+//== expect-stdout:
+// Hello, world!
+// 这玩意支持 UTF 8 么
+// 1.234560
+// 114514
+// 1
+//== expect-exitcode: 0
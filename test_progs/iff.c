@@ -16,3 +16,11 @@ void main() {
   if (x != y)
     print("x!=y");
 }
+
+//== stdin:
+// 1 2
+//== expect-stdout:
+// x<y
+// x<=y
+// x!=y
+//== expect-exitcode: 0
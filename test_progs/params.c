@@ -9,3 +9,9 @@ void main(){
     print("int d = ",d);
     print("c + d = ",c+d);
 }
+
+//== expect-stdout:
+// int c =  1
+// int d =  2
+// c + d =  3
+//== expect-exitcode: 0
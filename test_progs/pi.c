@@ -35,3 +35,20 @@ int main() {
   print(iter - 1, halfpi);
   return 0;
 }
+
+//== stdin:
+// 0.001
+//== expect-stdout:
+// 1 2.000000
+// 2 2.666667
+// 3 2.933333
+// 4 3.047619
+// 5 3.098413
+// 6 3.121501
+// 7 3.132157
+// 8 3.137130
+// 9 3.139470
+// 10 3.140578
+// 11 3.141106
+// 11 3.141106
+//== expect-exitcode: 0
@@ -5,3 +5,13 @@ int main() {
       "=============\n| #  /\\ + \" |\n|  /:\"\"\'\\\\^||\n|/|\\?\"\'/\\  !|\n|\\r \\ #s    |\n|\\a /\\  /\\\\v|\n=============");
   return 0;
 }
+
+//== expect-stdout:
+// =============
+// | #  /\ + " |
+// |  /:""'\\^||
+// |/|\?"'/\  !|
+// |\r \ #s    |
+// |\a /\  /\\v|
+// =============
+//== expect-exitcode: 0
@@ -18,3 +18,12 @@ int main() {
   }
   return 0;
 }
+
+//== stdin:
+// 1
+//== expect-stdout:
+// \
+// /
+//
+//
+//== expect-exitcode: 0